@@ -0,0 +1,255 @@
+//! Minimal constant-product AMM used as a stand-in for Jupiter on localnet.
+//!
+//! It declares the Jupiter program id so it can be loaded at that address through
+//! `[[test.genesis]]` in `Anchor.toml`. The fund program CPIs into it exactly like it
+//! does into Jupiter: an opaque instruction whose accounts are the caller's remaining
+//! accounts, with the fund's `program_authority` signing as the token owner.
+#![allow(clippy::result_large_err)]
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+
+pub const POOL_SEED: &[u8] = b"pool";
+pub const POOL_VAULT_SEED: &[u8] = b"pool_vault";
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+#[program]
+pub mod mock_swap {
+    use super::*;
+
+    pub fn initialize_pool(ctx: Context<InitializePool>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, MockSwapError::InvalidFee);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.mint_a = ctx.accounts.mint_a.key();
+        pool.mint_b = ctx.accounts.mint_b.key();
+        pool.vault_a = ctx.accounts.vault_a.key();
+        pool.vault_b = ctx.accounts.vault_b.key();
+        pool.fee_bps = fee_bps;
+        pool.bump = *ctx.bumps.get("pool").unwrap();
+
+        msg!("Pool created for {} / {}", pool.mint_a, pool.mint_b);
+        Ok(())
+    }
+
+    /// Seeds the pool reserves. Liquidity is not tracked per provider, this is a test fixture.
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64) -> Result<()> {
+        let accounts = &ctx.accounts;
+        for (from, to, amount) in [
+            (&accounts.provider_a, &accounts.vault_a, amount_a),
+            (&accounts.provider_b, &accounts.vault_b, amount_b),
+        ] {
+            token::transfer(
+                CpiContext::new(
+                    accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: from.to_account_info(),
+                        to: to.to_account_info(),
+                        authority: accounts.provider.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, minimum_amount_out: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let (vault_in, vault_out) = if ctx.accounts.source.mint == pool.mint_a {
+            (&ctx.accounts.vault_a, &ctx.accounts.vault_b)
+        } else {
+            (&ctx.accounts.vault_b, &ctx.accounts.vault_a)
+        };
+        require_keys_eq!(vault_out.mint, ctx.accounts.destination.mint, MockSwapError::MintMismatch);
+
+        let amount_out = quote_out(amount_in, vault_in.amount, vault_out.amount, pool.fee_bps)
+            .ok_or(MockSwapError::MathOverflow)?;
+        msg!("Swapping {} in for {} out", amount_in, amount_out);
+        require!(amount_out > 0, MockSwapError::ZeroOutput);
+        require!(amount_out >= minimum_amount_out, MockSwapError::SlippageExceeded);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.source.to_account_info(),
+                    to: vault_in.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let mint_a = pool.mint_a;
+        let mint_b = pool.mint_b;
+        let pool_seeds: &[&[u8]] = &[POOL_SEED, mint_a.as_ref(), mint_b.as_ref(), &[pool.bump]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: vault_out.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &[pool_seeds],
+            ),
+            amount_out,
+        )
+    }
+}
+
+/// Output of a constant-product swap after taking `fee_bps` off the input.
+pub fn quote_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Option<u64> {
+    let amount_in_after_fee = (amount_in as u128)
+        .checked_mul(10_000u128.checked_sub(fee_bps as u128)?)?
+        / 10_000;
+    let numerator = amount_in_after_fee.checked_mul(reserve_out as u128)?;
+    let denominator = (reserve_in as u128).checked_add(amount_in_after_fee)?;
+    if denominator == 0 {
+        return Some(0);
+    }
+    u64::try_from(numerator / denominator).ok()
+}
+
+#[account]
+pub struct Pool {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub fee_bps: u16,
+    pub bump: u8,
+}
+
+impl Pool {
+    pub const LEN: usize = 8 + 32 * 4 + 2 + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Pool::LEN,
+        seeds = [POOL_SEED, mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    pub mint_a: Account<'info, Mint>,
+    pub mint_b: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [POOL_VAULT_SEED, pool.key().as_ref(), mint_a.key().as_ref()],
+        bump,
+        token::mint = mint_a,
+        token::authority = pool
+    )]
+    pub vault_a: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [POOL_VAULT_SEED, pool.key().as_ref(), mint_b.key().as_ref()],
+        bump,
+        token::mint = mint_b,
+        token::authority = pool
+    )]
+    pub vault_b: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(has_one = vault_a, has_one = vault_b)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut)]
+    pub vault_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_b: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub provider_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub provider_b: Account<'info, TokenAccount>,
+
+    pub provider: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(has_one = vault_a, has_one = vault_b)]
+    pub pool: Account<'info, Pool>,
+
+    /// Owner of `source`; the fund program signs for its PDA authority
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = source.owner == authority.key() @ MockSwapError::InvalidOwner,
+        constraint = source.mint == pool.mint_a || source.mint == pool.mint_b @ MockSwapError::MintMismatch
+    )]
+    pub source: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub destination: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub vault_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub vault_b: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[error_code]
+pub enum MockSwapError {
+    #[msg("Fee must not exceed 10%")]
+    InvalidFee,
+    #[msg("Token account mint does not belong to this pool")]
+    MintMismatch,
+    #[msg("Source account is not owned by the signing authority")]
+    InvalidOwner,
+    #[msg("Swap would return nothing")]
+    ZeroOutput,
+    #[msg("Swap returned less than the minimum amount out")]
+    SlippageExceeded,
+    #[msg("Math overflow")]
+    MathOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_follows_constant_product() {
+        // 1_000 in against 10_000 / 10_000 reserves, no fee: 10_000 * 1_000 / 11_000
+        assert_eq!(quote_out(1_000, 10_000, 10_000, 0), Some(909));
+    }
+
+    #[test]
+    fn quote_takes_fee_from_input() {
+        // 30 bps leaves 997 of the 1_000 in
+        assert_eq!(quote_out(1_000, 10_000, 10_000, 30), Some(9_970_000 / 10_997));
+    }
+
+    #[test]
+    fn quote_never_drains_the_pool() {
+        assert!(quote_out(u64::MAX, 1, 1_000, 0).unwrap() < 1_000);
+        assert_eq!(quote_out(1_000, 0, 0, 0), Some(0));
+    }
+}
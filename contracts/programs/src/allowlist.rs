@@ -0,0 +1,179 @@
+use anchor_lang::prelude::*;
+
+use crate::events::{AllowlistApplied, AllowlistProposed};
+use crate::nav::holding_amounts;
+use crate::{FundDetails, FundError};
+
+pub const ALLOWLIST_SEED: &[u8] = b"allowlist";
+pub const MAX_ALLOWED_MINTS: usize = 16;
+// Investors get this long to redeem before a new allowlist takes effect
+pub const ALLOWLIST_TIMELOCK_SECONDS: i64 = 60 * 60 * 24 * 2;
+
+/// Mints a fund may trade, at `[ALLOWLIST_SEED, fund]`. Set at `create_fund`, after which
+/// changes are queued by the fund authority and only apply once the timelock has passed.
+#[account]
+pub struct AssetAllowlist {
+    pub fund: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub pending_mints: Vec<Pubkey>,
+    pub pending_apply_after: i64, // 0 when no change is queued
+    pub bump: u8,
+}
+
+impl AssetAllowlist {
+    pub const LEN: usize = 8 + 32 + (4 + 32 * MAX_ALLOWED_MINTS) * 2 + 8 + 1;
+
+    pub fn initialize(&mut self, fund: Pubkey, mints: Vec<Pubkey>, bump: u8) -> Result<()> {
+        validate_mints(&mints)?;
+        self.fund = fund;
+        self.mints = mints;
+        self.pending_mints = Vec::new();
+        self.pending_apply_after = 0;
+        self.bump = bump;
+        Ok(())
+    }
+
+    /// SOL is what the vault holds, so wrapped SOL is always tradable.
+    pub fn is_allowed(&self, mint: &Pubkey) -> bool {
+        *mint == spl_token::native_mint::id() || self.mints.contains(mint)
+    }
+}
+
+fn validate_mints(mints: &[Pubkey]) -> Result<()> {
+    if mints.len() > MAX_ALLOWED_MINTS {
+        return err!(FundError::InvalidAllowlist);
+    }
+    for (i, mint) in mints.iter().enumerate() {
+        if *mint == Pubkey::default() || mints[..i].contains(mint) {
+            return err!(FundError::InvalidAllowlist);
+        }
+    }
+    Ok(())
+}
+
+/// The fund's holdings once `allowed` applies: a dropped mint is fine if the fund no longer holds
+/// any of it and is removed, so NAV stops pricing it. `amounts` are the holdings' balances.
+fn holdings_after(holdings: &[Pubkey], amounts: &[u64], allowed: &[Pubkey]) -> Result<Vec<Pubkey>> {
+    let mut kept = Vec::with_capacity(holdings.len());
+    for (held, amount) in holdings.iter().zip(amounts) {
+        if *held == spl_token::native_mint::id() || allowed.contains(held) {
+            kept.push(*held);
+        } else if *amount > 0 {
+            // Dropping a mint the fund still holds would leave that position impossible to trade out of
+            msg!("Fund still holds {} of {}", amount, held);
+            return err!(FundError::AllowlistDropsHolding);
+        } else {
+            msg!("Dropping empty holding {}", held);
+        }
+    }
+    Ok(kept)
+}
+
+pub fn process_propose_allowlist(ctx: Context<ProposeAllowlist>, mints: Vec<Pubkey>) -> Result<()> {
+    validate_mints(&mints)?;
+
+    let allowlist = &mut ctx.accounts.asset_allowlist;
+    allowlist.pending_apply_after = Clock::get()?
+        .unix_timestamp
+        .checked_add(ALLOWLIST_TIMELOCK_SECONDS)
+        .ok_or(FundError::OverflowError)?;
+    allowlist.pending_mints = mints;
+
+    msg!(
+        "Allowlist change with {} mints queued until {}",
+        allowlist.pending_mints.len(),
+        allowlist.pending_apply_after
+    );
+    emit_cpi!(AllowlistProposed {
+        fund: allowlist.fund,
+        mints: allowlist.pending_mints.clone(),
+        apply_after: allowlist.pending_apply_after,
+    });
+    Ok(())
+}
+
+/// Permissionless once the timelock has elapsed. Takes the fund's holding token accounts as
+/// remaining accounts, in `holdings` order, so dropped mints can be checked to be empty.
+pub fn process_apply_allowlist(ctx: Context<ApplyAllowlist>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let allowlist = &mut ctx.accounts.asset_allowlist;
+    if allowlist.pending_apply_after == 0 {
+        return err!(FundError::NoPendingChange);
+    }
+    if now < allowlist.pending_apply_after {
+        return err!(FundError::TimelockNotElapsed);
+    }
+
+    let amounts = holding_amounts(
+        ctx.program_id,
+        &ctx.accounts.fund_details,
+        &ctx.accounts.fund_details.key(),
+        ctx.remaining_accounts,
+    )?;
+    let pending = std::mem::take(&mut allowlist.pending_mints);
+    ctx.accounts.fund_details.holdings = holdings_after(&ctx.accounts.fund_details.holdings, &amounts, &pending)?;
+
+    allowlist.mints = pending;
+    allowlist.pending_apply_after = 0;
+    msg!("Allowlist updated to {} mints", allowlist.mints.len());
+    emit_cpi!(AllowlistApplied {
+        fund: allowlist.fund,
+        mints: allowlist.mints.clone(),
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(fund_id: String)]
+pub struct ProposeAllowlist<'info> {
+    #[account(
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_details.authority.as_ref()],
+        bump,
+        constraint = fund_details.roles.owner == authority.key() @ FundError::Unauthorized
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        mut,
+        seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()],
+        bump = asset_allowlist.bump
+    )]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ApplyAllowlist<'info> {
+    #[account(mut)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        mut,
+        seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()],
+        bump = asset_allowlist.bump
+    )]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_empty_holdings_can_leave_the_allowlist() {
+        let (usdc, bonk) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let sol = spl_token::native_mint::id();
+        let holdings = [usdc, bonk, sol];
+
+        assert_eq!(holdings_after(&holdings, &[5, 0, 7], &[usdc]).unwrap(), vec![usdc, sol]);
+        assert_eq!(holdings_after(&holdings, &[5, 1, 7], &[usdc, bonk]).unwrap(), holdings.to_vec());
+        assert_eq!(
+            holdings_after(&holdings, &[5, 1, 7], &[usdc]).unwrap_err(),
+            error!(FundError::AllowlistDropsHolding)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use anchor_lang::prelude::*;
+
+use crate::events::ProtocolConfigUpdated;
+use crate::fees::FeeLimits;
+use crate::multisig::{execute_approved, Multisig, PendingAction, PrivilegedAction, MULTISIG_SEED, PENDING_ACTION_SEED};
+use crate::program::First;
+use crate::FundError;
+
+pub const PROTOCOL_CONFIG_SEED: &[u8] = b"protocol_config";
+
+/// Singleton holding the protocol-wide privileged addresses. After bootstrapping the
+/// multisig, `admin` can no longer change anything here on its own.
+#[account]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey, // Pubkey::default() when no transfer is in flight
+    pub treasury: Pubkey,      // Receives drained fund assets
+    pub fee_recipient: Pubkey, // Claims the `[PROTOCOL_FEE_VAULT_SEED]` fee vault
+    pub fee_limits: FeeLimits, // Caps on the fee schedule a fund may choose at creation
+    pub poll_oracle: Pubkey,   // Signs Telegram poll results; Pubkey::default() until registered
+    pub bump: u8,
+}
+
+impl ProtocolConfig {
+    pub const LEN: usize = 8 + 32 * 4 + FeeLimits::LEN + 32 + 1;
+
+    fn updated_event(&self) -> ProtocolConfigUpdated {
+        ProtocolConfigUpdated {
+            admin: self.admin,
+            pending_admin: self.pending_admin,
+            treasury: self.treasury,
+            fee_recipient: self.fee_recipient,
+            fee_limits: self.fee_limits,
+            poll_oracle: self.poll_oracle,
+        }
+    }
+}
+
+pub fn process_initialize_protocol(ctx: Context<InitializeProtocol>, treasury: Pubkey, fee_recipient: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.protocol_config;
+    config.admin = ctx.accounts.admin.key();
+    config.pending_admin = Pubkey::default();
+    config.treasury = treasury;
+    config.fee_recipient = fee_recipient;
+    config.fee_limits = FeeLimits::DEFAULT;
+    config.poll_oracle = Pubkey::default();
+    config.bump = *ctx.bumps.get("protocol_config").unwrap();
+
+    msg!("Protocol initialized with admin {}", config.admin);
+    emit_cpi!(config.updated_event());
+    Ok(())
+}
+
+pub fn process_set_protocol_roles(ctx: Context<UpdateProtocolConfig>, treasury: Pubkey, fee_recipient: Pubkey) -> Result<()> {
+    let executed = execute_approved(
+        &ctx.accounts.multisig,
+        &mut ctx.accounts.pending_action,
+        PrivilegedAction::SetProtocolRoles { treasury, fee_recipient },
+    )?;
+    emit_cpi!(executed);
+
+    let config = &mut ctx.accounts.protocol_config;
+    config.treasury = treasury;
+    config.fee_recipient = fee_recipient;
+
+    msg!("Protocol roles updated: treasury {}, fee recipient {}", treasury, fee_recipient);
+    emit_cpi!(config.updated_event());
+    Ok(())
+}
+
+/// Only bounds funds created from now on; existing funds keep the schedule they were created with.
+pub fn process_set_fee_limits(ctx: Context<UpdateProtocolConfig>, fee_limits: FeeLimits) -> Result<()> {
+    fee_limits.validate()?;
+    let executed = execute_approved(
+        &ctx.accounts.multisig,
+        &mut ctx.accounts.pending_action,
+        PrivilegedAction::SetFeeLimits { fee_limits },
+    )?;
+    emit_cpi!(executed);
+
+    ctx.accounts.protocol_config.fee_limits = fee_limits;
+    msg!("Fee limits updated to {:?}", fee_limits);
+    emit_cpi!(ctx.accounts.protocol_config.updated_event());
+    Ok(())
+}
+
+/// Poll results already recorded stay valid after a rotation; only new ones need the new key.
+pub fn process_set_poll_oracle(ctx: Context<UpdateProtocolConfig>, poll_oracle: Pubkey) -> Result<()> {
+    let executed = execute_approved(
+        &ctx.accounts.multisig,
+        &mut ctx.accounts.pending_action,
+        PrivilegedAction::SetPollOracle { poll_oracle },
+    )?;
+    emit_cpi!(executed);
+
+    ctx.accounts.protocol_config.poll_oracle = poll_oracle;
+    msg!("Poll oracle set to {}", poll_oracle);
+    emit_cpi!(ctx.accounts.protocol_config.updated_event());
+    Ok(())
+}
+
+/// First step of an admin transfer. Passing `Pubkey::default()` cancels a pending transfer.
+pub fn process_propose_protocol_admin(ctx: Context<UpdateProtocolConfig>, new_admin: Pubkey) -> Result<()> {
+    let executed = execute_approved(
+        &ctx.accounts.multisig,
+        &mut ctx.accounts.pending_action,
+        PrivilegedAction::ProposeProtocolAdmin { new_admin },
+    )?;
+    emit_cpi!(executed);
+
+    ctx.accounts.protocol_config.pending_admin = new_admin;
+    msg!("Proposed new protocol admin {}", new_admin);
+    emit_cpi!(ctx.accounts.protocol_config.updated_event());
+    Ok(())
+}
+
+pub fn process_accept_protocol_admin(ctx: Context<AcceptProtocolAdmin>) -> Result<()> {
+    let config = &mut ctx.accounts.protocol_config;
+    config.admin = config.pending_admin;
+    config.pending_admin = Pubkey::default();
+    msg!("Protocol admin is now {}", config.admin);
+    emit_cpi!(config.updated_event());
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeProtocol<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = ProtocolConfig::LEN,
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    // Only the program's upgrade authority can bootstrap the config
+    #[account(constraint = first_program.programdata_address()? == Some(program_data.key()))]
+    pub first_program: Program<'info, First>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ FundError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    #[account(mut, seeds = [PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [MULTISIG_SEED],
+        bump = multisig.bump,
+        constraint = multisig.is_signer(&executor.key()) @ FundError::Unauthorized
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, &pending_action.id.to_le_bytes()],
+        bump = pending_action.bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+
+    pub executor: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptProtocolAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = protocol_config.bump,
+        constraint = protocol_config.pending_admin == pending_admin.key() @ FundError::Unauthorized
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub pending_admin: Signer<'info>,
+}
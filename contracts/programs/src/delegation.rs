@@ -0,0 +1,303 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::{self, TokenAccount};
+use solana_program::{program::invoke_signed, system_instruction};
+
+use crate::events::{DelegationRevoked, VotesDelegated};
+use crate::governance::{VoteRecord, VOTE_SEED};
+use crate::queue::{VetoRecord, VETO_SEED};
+use crate::position::InvestorPosition;
+use crate::{FundDetails, FundError};
+
+pub const DELEGATION_SEED: &[u8] = b"delegation";
+// Accounts `cast_vote` and `veto_trade` expect per delegator in their remaining accounts
+pub const DELEGATED_VOTE_ACCOUNTS: usize = 4;
+
+/// A holder's delegation of their voting weight in one fund, at
+/// `[DELEGATION_SEED, fund, delegator]`. Only the delegate is stored, never a weight: the
+/// delegator's shares are counted when the delegate votes, so shares moved since then are
+/// not counted.
+#[account]
+pub struct Delegation {
+    pub fund: Pubkey,
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    pub delegated_at: i64,
+    pub bump: u8,
+}
+
+impl Delegation {
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 1;
+}
+
+/// Delegates the holder's voting weight in a fund to `delegate`, replacing any earlier
+/// delegation.
+pub fn process_delegate_votes(ctx: Context<DelegateVotes>, delegate: Pubkey) -> Result<()> {
+    let delegator = ctx.accounts.delegator.key();
+    if delegate == delegator || delegate == Pubkey::default() {
+        return err!(FundError::InvalidDelegation);
+    }
+    let now = Clock::get()?.unix_timestamp;
+
+    let delegation = &mut ctx.accounts.delegation;
+    delegation.fund = ctx.accounts.fund_details.key();
+    delegation.delegator = delegator;
+    delegation.delegate = delegate;
+    delegation.delegated_at = now;
+    delegation.bump = *ctx.bumps.get("delegation").unwrap();
+
+    msg!("{} delegated votes to {}", delegator, delegate);
+    emit_cpi!(VotesDelegated {
+        fund: ctx.accounts.delegation.fund,
+        delegator,
+        delegate,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+/// Ends a delegation and returns its rent. Votes the delegate already cast for the holder
+/// stand, and the holder cannot vote again on those proposals.
+pub fn process_revoke_delegation(ctx: Context<RevokeDelegation>) -> Result<()> {
+    msg!("{} revoked delegation to {}", ctx.accounts.delegator.key(), ctx.accounts.delegation.delegate);
+    emit_cpi!(DelegationRevoked {
+        fund: ctx.accounts.delegation.fund,
+        delegator: ctx.accounts.delegator.key(),
+        delegate: ctx.accounts.delegation.delegate,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Counts the weight delegated to `voter` on `proposal`, returning the total.
+///
+/// `accounts` holds `[delegation, position, token_account, vote_record]` per delegator: the
+/// delegator's `Delegation` to `voter`, their `InvestorPosition` and fund-share ATA, and the
+/// uninitialized `[VOTE_SEED, proposal, delegator]` PDA. A `VoteRecord` is created there for
+/// every delegator, exactly as if they had voted themselves, so each holder's shares count
+/// once per proposal whether they vote directly or through any delegate.
+#[allow(clippy::too_many_arguments)]
+pub fn record_delegated_votes<'info>(
+    program_id: &Pubkey,
+    fund_details: &FundDetails,
+    fund_key: &Pubkey,
+    proposal: &Pubkey,
+    voter: &AccountInfo<'info>,
+    in_favor: bool,
+    accounts: &[AccountInfo<'info>],
+    system_program: &AccountInfo<'info>,
+) -> Result<u64> {
+    let chunks = accounts.chunks_exact(DELEGATED_VOTE_ACCOUNTS);
+    if !chunks.remainder().is_empty() {
+        return err!(FundError::InvalidDelegation);
+    }
+    let rent = Rent::get()?;
+
+    let mut total: u64 = 0;
+    for chunk in chunks {
+        let (delegator, weight) = delegated_weight(program_id, fund_details, fund_key, voter, chunk)?;
+        let vote_info = &chunk[3];
+
+        let (expected_vote, vote_bump) =
+            Pubkey::find_program_address(&[VOTE_SEED, proposal.as_ref(), delegator.as_ref()], program_id);
+        if vote_info.key() != expected_vote {
+            return err!(FundError::InvalidDelegation);
+        }
+        if !vote_info.data_is_empty() {
+            msg!("{} has already voted on this proposal", delegator);
+            return err!(FundError::AlreadyVoted);
+        }
+        create_record_account(
+            program_id,
+            voter,
+            vote_info,
+            system_program,
+            &rent,
+            VoteRecord::LEN,
+            &[VOTE_SEED, proposal.as_ref(), delegator.as_ref(), &[vote_bump]],
+        )?;
+        let record = VoteRecord {
+            proposal: *proposal,
+            voter: delegator,
+            cast_by: voter.key(),
+            weight,
+            in_favor,
+            bump: vote_bump,
+        };
+        record.try_serialize(&mut vote_info.try_borrow_mut_data()?.as_mut())?;
+
+        total = total.checked_add(weight).ok_or(FundError::OverflowError)?;
+    }
+    Ok(total)
+}
+
+/// Counts the weight delegated to `holder` against `queued_trade`, returning the total.
+///
+/// Takes the same accounts as `record_delegated_votes`, with the uninitialized
+/// `[VETO_SEED, queued_trade, delegator]` PDA in place of the vote record. A `VetoRecord` is
+/// created there for every delegator, so each holder's shares veto a trade at most once.
+pub fn record_delegated_vetoes<'info>(
+    program_id: &Pubkey,
+    fund_details: &FundDetails,
+    fund_key: &Pubkey,
+    queued_trade: &Pubkey,
+    holder: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    system_program: &AccountInfo<'info>,
+) -> Result<u64> {
+    let chunks = accounts.chunks_exact(DELEGATED_VOTE_ACCOUNTS);
+    if !chunks.remainder().is_empty() {
+        return err!(FundError::InvalidDelegation);
+    }
+    let rent = Rent::get()?;
+
+    let mut total: u64 = 0;
+    for chunk in chunks {
+        let (delegator, weight) = delegated_weight(program_id, fund_details, fund_key, holder, chunk)?;
+        let veto_info = &chunk[3];
+
+        let (expected_veto, veto_bump) =
+            Pubkey::find_program_address(&[VETO_SEED, queued_trade.as_ref(), delegator.as_ref()], program_id);
+        if veto_info.key() != expected_veto {
+            return err!(FundError::InvalidDelegation);
+        }
+        if !veto_info.data_is_empty() {
+            msg!("{} has already vetoed this trade", delegator);
+            return err!(FundError::AlreadyVoted);
+        }
+        create_record_account(
+            program_id,
+            holder,
+            veto_info,
+            system_program,
+            &rent,
+            VetoRecord::LEN,
+            &[VETO_SEED, queued_trade.as_ref(), delegator.as_ref(), &[veto_bump]],
+        )?;
+        let record = VetoRecord {
+            queued_trade: *queued_trade,
+            holder: delegator,
+            cast_by: holder.key(),
+            weight,
+            bump: veto_bump,
+        };
+        record.try_serialize(&mut veto_info.try_borrow_mut_data()?.as_mut())?;
+
+        total = total.checked_add(weight).ok_or(FundError::OverflowError)?;
+    }
+    Ok(total)
+}
+
+/// Checks one `[delegation, position, token_account, ..]` chunk delegates to `delegate` in
+/// this fund and returns the delegator with the shares they both hold and have recorded.
+fn delegated_weight(
+    program_id: &Pubkey,
+    fund_details: &FundDetails,
+    fund_key: &Pubkey,
+    delegate: &AccountInfo,
+    chunk: &[AccountInfo],
+) -> Result<(Pubkey, u64)> {
+    let (delegation_info, position_info, token_info) = (&chunk[0], &chunk[1], &chunk[2]);
+
+    if delegation_info.owner != program_id {
+        return err!(FundError::InvalidDelegation);
+    }
+    let delegation = Delegation::try_deserialize(&mut delegation_info.try_borrow_data()?.as_ref())?;
+    if delegation.fund != *fund_key || delegation.delegate != delegate.key() {
+        return err!(FundError::InvalidDelegation);
+    }
+    let delegator = delegation.delegator;
+
+    if position_info.owner != program_id {
+        return err!(FundError::InvalidDelegation);
+    }
+    let position = InvestorPosition::try_deserialize(&mut position_info.try_borrow_data()?.as_ref())?;
+    if position.fund != *fund_key || position.investor != delegator {
+        return err!(FundError::InvalidDelegation);
+    }
+
+    if token_info.owner != &token::ID
+        || token_info.key() != get_associated_token_address(&delegator, &fund_details.fund_token_mint)
+    {
+        return err!(FundError::InvalidDelegation);
+    }
+    let balance = TokenAccount::try_deserialize(&mut token_info.try_borrow_data()?.as_ref())?.amount;
+    Ok((delegator, position.shares.min(balance)))
+}
+
+/// Creates a `space`-byte PDA owned by this program at `record_info`, like Anchor's `init`
+/// does, including when someone has already sent it lamports.
+fn create_record_account<'info>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'info>,
+    record_info: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    space: usize,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let required = rent.minimum_balance(space);
+    let current = record_info.lamports();
+    if current == 0 {
+        invoke_signed(
+            &system_instruction::create_account(payer.key, record_info.key, required, space as u64, program_id),
+            &[payer.clone(), record_info.clone(), system_program.clone()],
+            &[seeds],
+        )?;
+        return Ok(());
+    }
+
+    if current < required {
+        invoke_signed(
+            &system_instruction::transfer(payer.key, record_info.key, required - current),
+            &[payer.clone(), record_info.clone(), system_program.clone()],
+            &[],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(record_info.key, space as u64),
+        &[record_info.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(record_info.key, program_id),
+        &[record_info.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DelegateVotes<'info> {
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        init_if_needed,
+        payer = delegator,
+        space = Delegation::LEN,
+        seeds = [DELEGATION_SEED, fund_details.key().as_ref(), delegator.key().as_ref()],
+        bump
+    )]
+    pub delegation: Account<'info, Delegation>,
+
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RevokeDelegation<'info> {
+    #[account(
+        mut,
+        close = delegator,
+        seeds = [DELEGATION_SEED, delegation.fund.as_ref(), delegator.key().as_ref()],
+        bump = delegation.bump
+    )]
+    pub delegation: Account<'info, Delegation>,
+
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+}
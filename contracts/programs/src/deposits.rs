@@ -3,6 +3,11 @@ pub use anchor_lang::prelude::*;
 
 pub use anchor_spl::token::{self, Token, TokenAccount, Transfer};
 pub use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use crate::events::{Deposited, StatusChanged};
+use crate::fees::mint_fee_shares;
+use crate::nav::{circulating_shares, compute_nav, load_nav_accounts, shares_for_deposit};
+use crate::fee_vault::{FeeVault, MANAGER_FEE_VAULT_SEED, PROTOCOL_FEE_VAULT_SEED};
+use crate::position::{InvestorPosition, POSITION_SEED};
 use crate::FundDetails;
 use crate::Mint;
 use anchor_spl::token::MintTo;
@@ -12,12 +17,12 @@ use solana_program::system_instruction;
 use solana_program::program::invoke;
 
 
-use crate::{FUND_STATUS_ACTIVE, FUND_STATUS_TRADE, FUND_STATUS_EXPIRED, THREE_MONTHS_SECONDS, FundError, VAULT_SEED};
+use crate::lifecycle::FundStatus;
+use crate::{THREE_MONTHS_SECONDS, FundError, VAULT_SEED};
 
-pub fn process_deposit(ctx: Context<DepositLiquidity>, amount: u64, fund_id: String, fund_creator: Pubkey, manager_address: Pubkey, tvl: u64) -> Result<()> {
+pub fn process_deposit(ctx: Context<DepositLiquidity>, amount: u64, fund_id: String, fund_creator: Pubkey, manager_address: Pubkey) -> Result<()> {
     msg!("Deposit amount in lamports: {}", amount);
-    msg!("Current TVL in lamports: {}", tvl);
-    msg!("Printing all values of params amount {} fund id {}, manager {}  this is tvl ,{}" ,amount,fund_id, manager_address,tvl);
+    msg!("Printing all values of params amount {} fund id {}, manager {}" ,amount,fund_id, manager_address);
     let fund_threshold = ctx.accounts.fund_details.invest_threshold;
     let current_total = ctx.accounts.fund_details.total_deposit;
     
@@ -26,25 +31,48 @@ pub fn process_deposit(ctx: Context<DepositLiquidity>, amount: u64, fund_id: Str
     let fund_start_time = ctx.accounts.fund_details.start_time;
     let fund_age = current_time - fund_start_time;
     
-    // Check if fund is expired
+    // Check if fund is expired. The status itself is advanced by `crank_lifecycle`.
     if fund_age > THREE_MONTHS_SECONDS {
-        ctx.accounts.fund_details.status = FUND_STATUS_EXPIRED;
         return err!(FundError::FundExpired);
     }
     
-    // Only block deposits if the fund is EXPIRED
-    if ctx.accounts.fund_details.status == FUND_STATUS_EXPIRED {
+    // Only fundraising and trading funds take deposits, counting a soft close that is due
+    // but not cranked yet
+    if !ctx.accounts.fund_details.effective_status(current_time).accepts_deposits() {
         return err!(FundError::InvalidFundStatus);
     }
-   
 
-    
-    let total_fee = amount.checked_div(100).ok_or(FundError::OverflowError)?;
-    let remaining_amount = amount.checked_sub(total_fee).ok_or(FundError::OverflowError)?;
+    // A fundraise that missed its deadline is refunding investors and takes no new money
+    if ctx.accounts.fund_details.fundraise_failed(current_time) {
+        return err!(FundError::FundraiseClosed);
+    }
+
+    // Value the fund before this deposit lands in the vault
+    let (holding_accounts, oracle) = load_nav_accounts(
+        ctx.program_id,
+        &ctx.accounts.fund_details,
+        ctx.remaining_accounts,
+        current_time,
+    )?;
+    let nav = compute_nav(
+        ctx.program_id,
+        &ctx.accounts.fund_details,
+        &ctx.accounts.fund_details.key(),
+        ctx.accounts.fund_vault.lamports(),
+        holding_accounts,
+        &oracle,
+    )?;
+    let mut outstanding_shares = circulating_shares(
+        ctx.accounts.fund_token_mint.supply,
+        ctx.accounts.fund_token_account.amount,
+    );
+    msg!("Current NAV in lamports: {}, outstanding shares: {}", nav, outstanding_shares);
 
-    // Split fees (20% to manager, 80% to hedge owner)
-    let manager_fee = total_fee.checked_mul(20).ok_or(FundError::OverflowError)? / 100;
-    let owner_fee = total_fee.checked_sub(manager_fee).ok_or(FundError::OverflowError)?;
+    // Split the deposit fee between the manager and the protocol per the fund's schedule
+    let fees = ctx.accounts.fund_details.fee_schedule.deposit_fees(amount)?;
+    let remaining_amount = fees.net;
+    let manager_fee = fees.manager_fee;
+    let owner_fee = fees.protocol_fee;
 
     // Transfer SOL from user to the VAULT (not to fund_details)
     invoke(
@@ -62,106 +90,101 @@ pub fn process_deposit(ctx: Context<DepositLiquidity>, amount: u64, fund_id: Str
     
     msg!("Transferred {} lamports to fund vault", remaining_amount);
 
-    // Transfer manager fee
+    // Transfer manager fee into the manager's fee vault
     invoke(
         &system_instruction::transfer(
             &ctx.accounts.user.key(),
-            &ctx.accounts.fund_creator_account.key(),
+            &ctx.accounts.manager_fee_vault.key(),
             manager_fee,
         ),
         &[
             ctx.accounts.user.to_account_info(),
-            ctx.accounts.fund_creator_account.to_account_info(),
+            ctx.accounts.manager_fee_vault.to_account_info(),
             ctx.accounts.system_program.to_account_info(),
         ],
     )?;
 
-    // Transfer hedge owner fee
+    // Transfer protocol fee into the protocol fee vault
     invoke(
         &system_instruction::transfer(
             &ctx.accounts.user.key(),
-            &ctx.accounts.hedge_fund_owner.key(),
+            &ctx.accounts.protocol_fee_vault.key(),
             owner_fee,
         ),
         &[
             ctx.accounts.user.to_account_info(),
-            ctx.accounts.hedge_fund_owner.to_account_info(),
+            ctx.accounts.protocol_fee_vault.to_account_info(),
             ctx.accounts.system_program.to_account_info(),
         ],
     )?;
 
+    // Both fee vaults only count what has actually been paid into them
+    let fee_recipient = ctx.accounts.fee_recipient.key();
+    let manager_vault_bump = *ctx.bumps.get("manager_fee_vault").unwrap();
+    let manager_fee_vault = &mut ctx.accounts.manager_fee_vault;
+    manager_fee_vault.init_if_new(fee_recipient, manager_vault_bump);
+    manager_fee_vault.record_fee(manager_fee)?;
+    let protocol_vault_bump = *ctx.bumps.get("protocol_fee_vault").unwrap();
+    let protocol_fee_vault = &mut ctx.accounts.protocol_fee_vault;
+    protocol_fee_vault.init_if_new(Pubkey::default(), protocol_vault_bump);
+    protocol_fee_vault.record_fee(owner_fee)?;
+
     msg!("SOL transfers completed");
 
     let binding = fund_creator;
     let seeds = &[
         b"fund_details".as_ref(),
-        fund_id.as_bytes().as_ref(),
+        fund_id.as_bytes(),
         binding.as_ref(),
         &[*ctx.bumps.get("fund_details").unwrap()]  
     ];
 
+    // Settle management and performance fees at the pre-deposit NAV, so the new investor
+    // neither pays for past gains nor gets diluted by the fee shares afterwards
+    let fee_shares = ctx.accounts.fund_details.settle_fees(nav, outstanding_shares, current_time)?;
+    mint_fee_shares(
+        &ctx.accounts.fund_details,
+        seeds,
+        &ctx.accounts.fund_token_mint,
+        &ctx.accounts.manager_token_account,
+        &ctx.accounts.token_program,
+        fee_shares,
+    )?;
+    outstanding_shares = outstanding_shares.checked_add(fee_shares).ok_or(FundError::OverflowError)?;
+    let fund_details = &mut ctx.accounts.fund_details;
+    fund_details.tokens_minted = fund_details.tokens_minted.checked_add(fee_shares)
+        .ok_or(FundError::OverflowError)?;
+
     msg!("After SOL transfers completed");
     msg!("current_total, fund_threshold {:?} {:?}", current_total, fund_threshold);
     
+    // Shares are priced at NAV / outstanding shares
+    let tokens_to_transfer = shares_for_deposit(remaining_amount, nav, outstanding_shares)?;
+    if tokens_to_transfer == 0 {
+        return err!(FundError::DepositTooSmall);
+    }
+    msg!("Shares for this deposit: {}", tokens_to_transfer);
+
     if current_total < fund_threshold {
-        // Calculate FT price: TVL / Total FT Supply
-        let total_ft_supply = ctx.accounts.fund_details.tokens_minted;
-        if total_ft_supply == 0 {
-            // Initial deposit case - 1:1 ratio
-            let tokens_to_transfer = remaining_amount;
-            
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.fund_token_account.to_account_info(),
-                        to: ctx.accounts.user_token_account.to_account_info(),
-                        authority: ctx.accounts.fund_details.to_account_info(),
-                    },
-                    &[seeds]
-                ),
-                tokens_to_transfer
-            )?;
-
-            let fund_details = &mut ctx.accounts.fund_details;
-            fund_details.tokens_minted = fund_details.tokens_minted.checked_sub(tokens_to_transfer)
-                .ok_or(FundError::OverflowError)?;
-        } else {
-            // Calculate based on formula
-            let ft_price = tvl.checked_div(total_ft_supply).ok_or(FundError::OverflowError)?;
-            let tokens_to_transfer = remaining_amount.checked_div(ft_price).ok_or(FundError::OverflowError)?;
-
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.fund_token_account.to_account_info(),
-                        to: ctx.accounts.user_token_account.to_account_info(),
-                        authority: ctx.accounts.fund_details.to_account_info(),
-                    },
-                    &[seeds]
-                ),
-                tokens_to_transfer
-            )?;
-
-            let fund_details = &mut ctx.accounts.fund_details;
-            fund_details.tokens_minted = fund_details.tokens_minted.checked_sub(tokens_to_transfer)
-                .ok_or(FundError::OverflowError)?;
-        }
+        // Hand out shares from the fund's pre-minted inventory
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.fund_token_account.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.fund_details.to_account_info(),
+                },
+                &[seeds]
+            ),
+            tokens_to_transfer
+        )?;
+
+        let fund_details = &mut ctx.accounts.fund_details;
+        fund_details.tokens_minted = fund_details.tokens_minted.checked_sub(tokens_to_transfer)
+            .ok_or(FundError::OverflowError)?;
     } else {
         msg!("Inside else condition");
-        // Calculate new tokens based on updated TVL
-        let new_tvl = tvl.checked_add(remaining_amount).ok_or(FundError::OverflowError)?;
-        let total_ft_supply = ctx.accounts.fund_details.tokens_minted;
-        
-        // Calculate new tokens to mint based on the formula
-        let new_tokens = if total_ft_supply == 0 {
-            remaining_amount // 1:1 ratio for initial deposit
-        } else {
-            let ft_price = new_tvl.checked_div(total_ft_supply).ok_or(FundError::OverflowError)?;
-            remaining_amount.checked_div(ft_price).ok_or(FundError::OverflowError)?
-        };
-
         token::mint_to(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
@@ -172,11 +195,11 @@ pub fn process_deposit(ctx: Context<DepositLiquidity>, amount: u64, fund_id: Str
                 },
                 &[seeds]
             ),
-            new_tokens,
+            tokens_to_transfer,
         )?;
 
         let fund_details = &mut ctx.accounts.fund_details;
-        fund_details.tokens_minted = fund_details.tokens_minted.checked_add(new_tokens)
+        fund_details.tokens_minted = fund_details.tokens_minted.checked_add(tokens_to_transfer)
             .ok_or(FundError::OverflowError)?;
         fund_details.current_fund_token = fund_details.tokens_minted;
     }
@@ -187,28 +210,50 @@ pub fn process_deposit(ctx: Context<DepositLiquidity>, amount: u64, fund_id: Str
         .ok_or(FundError::OverflowError)?;
     fund_details.current_depost = fund_details.total_deposit;
     
-    // Check if the fund has reached its investment threshold and update status to TRADE if so
-    if fund_details.total_deposit >= fund_details.invest_threshold && fund_details.status == FUND_STATUS_ACTIVE {
-        fund_details.status = FUND_STATUS_TRADE;
-        msg!("Fund has reached investment threshold. Status updated to TRADE.");
+    // Check if the fund has reached its investment threshold and start trading if so
+    let threshold_met = fund_details.total_deposit >= fund_details.invest_threshold
+        && fund_details.status == FundStatus::Fundraising;
+    if threshold_met {
+        fund_details.transition_to(FundStatus::Trading)?;
+        msg!("Fund has reached investment threshold.");
     }
 
-    // Record user deposit
-    let mut fund_id_bytes = [0u8; 32];
-    fund_id_bytes[..fund_id.as_bytes().len()].copy_from_slice(fund_id.as_bytes());
-    
-    let user_deposit = UserDeposit {
-        user: ctx.accounts.user.key(),
-        deposit_amount: amount,
-        fund_id: fund_id_bytes,
-    };
-    ctx.accounts.fund_details.user_deposits.push(user_deposit);
+    // Record the deposit on the investor's position
+    let fund_key = ctx.accounts.fund_details.key();
+    let investor = ctx.accounts.user.key();
+    let position_bump = *ctx.bumps.get("position").unwrap();
+    let position = &mut ctx.accounts.position;
+    position.init_if_new(fund_key, investor, position_bump);
+    position.record_deposit(amount, remaining_amount, tokens_to_transfer, current_time)?;
+    msg!("Position now holds {} shares over {} deposits", position.shares, position.deposit_count);
 
+    emit_cpi!(Deposited {
+        fund: fund_key,
+        investor,
+        amount,
+        net_amount: remaining_amount,
+        manager_fee,
+        protocol_fee: owner_fee,
+        shares: tokens_to_transfer,
+        fee_shares,
+        nav,
+        outstanding_shares: outstanding_shares.saturating_add(tokens_to_transfer),
+        timestamp: current_time,
+    });
+    if threshold_met {
+        emit_cpi!(StatusChanged {
+            fund: fund_key,
+            from: FundStatus::Fundraising,
+            to: FundStatus::Trading,
+            timestamp: current_time,
+        });
+    }
     Ok(())
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(amount: u64, fund_id: String, fund_creator: Pubkey, manager_address: Pubkey, tvl: u64)]
+#[instruction(amount: u64, fund_id: String, fund_creator: Pubkey, manager_address: Pubkey)]
 pub struct DepositLiquidity<'info> {
     #[account(
         mut,
@@ -221,7 +266,7 @@ pub struct DepositLiquidity<'info> {
     // Add the fund vault account
     #[account(
         mut,
-        seeds = [VAULT_SEED, fund_id.as_bytes()],
+        seeds = [VAULT_SEED, fund_details.key().as_ref()],
         bump
     )]
     /// CHECK: This is a simple SOL vault owned by the system program
@@ -241,20 +286,48 @@ pub struct DepositLiquidity<'info> {
     )]
     pub user_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = InvestorPosition::LEN,
+        seeds = [POSITION_SEED, fund_details.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, InvestorPosition>,
+
     #[account(mut)]
     pub user: Signer<'info>,
     
-    /// CHECK: This is the fund creator's account to receive fees
-    #[account(mut, constraint = fund_creator_account.key() == fund_creator)]
-    pub fund_creator_account: AccountInfo<'info>,
+    /// CHECK: The fund's fee recipient, owner of the manager's fee vault and fee shares
+    #[account(address = fund_details.roles.fee_recipient)]
+    pub fee_recipient: AccountInfo<'info>,
 
-    /// CHECK: This is the hedge fund owner account
+    // Receives performance fee shares
     #[account(
-        mut, 
-        seeds = [b"fund_manager", manager_address.as_ref()],
+        init_if_needed,
+        payer = user,
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = fee_recipient,
+    )]
+    pub manager_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = FeeVault::LEN,
+        seeds = [MANAGER_FEE_VAULT_SEED, fund_details.roles.fee_recipient.as_ref()],
         bump
     )]
-    pub hedge_fund_owner: AccountInfo<'info>,
+    pub manager_fee_vault: Account<'info, FeeVault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = FeeVault::LEN,
+        seeds = [PROTOCOL_FEE_VAULT_SEED],
+        bump
+    )]
+    pub protocol_fee_vault: Account<'info, FeeVault>,
 
     // Make sure this is mutable to allow minting new tokens
     #[account(mut)]
@@ -265,10 +338,3 @@ pub struct DepositLiquidity<'info> {
     pub rent: Sysvar<'info, Rent>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
-pub struct UserDeposit {
-    pub user: Pubkey,
-    pub deposit_amount: u64,
-    pub fund_id: [u8; 32],
-}
\ No newline at end of file
@@ -0,0 +1,269 @@
+use anchor_lang::prelude::*;
+
+use crate::fees::{FeeLimits, FeeSchedule};
+use crate::governance::GovernanceConfig;
+use crate::lifecycle::FundStatus;
+use crate::multisig::PrivilegedAction;
+
+// Emitted through `emit_cpi!`, so they land in the transaction's inner instructions and
+// survive log truncation. Amounts are in lamports or base token units, times are unix seconds.
+
+#[event]
+pub struct FundCreated {
+    pub fund: Pubkey,
+    pub authority: Pubkey,
+    pub fund_token_mint: Pubkey,
+    pub initial_shares: u64,
+    pub invest_threshold: u64,
+    pub fundraise_deadline: i64,
+    pub fee_schedule: FeeSchedule,
+    pub governance: GovernanceConfig,
+    pub allowed_mints: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HoldingAdded {
+    pub fund: Pubkey,
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Deposited {
+    pub fund: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub net_amount: u64, // What reached the vault after fees
+    pub manager_fee: u64,
+    pub protocol_fee: u64,
+    pub shares: u64,
+    pub fee_shares: u64, // Management and performance fee shares minted to the manager
+    pub nav: u64,        // Before the deposit
+    pub outstanding_shares: u64, // After the deposit
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Redeemed {
+    pub fund: Pubkey,
+    pub investor: Pubkey,
+    pub shares: u64,
+    pub gross_amount: u64,
+    pub net_amount: u64, // Paid to the investor after fees
+    pub manager_fee: u64,
+    pub protocol_fee: u64,
+    pub fee_shares: u64,
+    pub nav: u64, // Before the redemption; the remaining final distribution once expired
+    pub outstanding_shares: u64, // After the redemption
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RefundClaimed {
+    pub fund: Pubkey,
+    pub investor: Pubkey,
+    pub shares: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TradeExecuted {
+    pub fund: Pubkey,
+    pub trader: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCreated {
+    pub fund: Pubkey,
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub voting_ends_at: i64,
+}
+
+#[event]
+pub struct VoteCast {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub weight: u64,           // Shares voted, including delegated ones
+    pub delegated_weight: u64, // Shares of holders delegating to the voter
+    pub in_favor: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PollResultRecorded {
+    pub fund: Pubkey,
+    pub poll_result: Pubkey,
+    pub poll_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct VotesDelegated {
+    pub fund: Pubkey,
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DelegationRevoked {
+    pub fund: Pubkey,
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TradeQueued {
+    pub fund: Pubkey,
+    pub queued_trade: Pubkey,
+    pub id: u64,
+    pub authorization: Pubkey, // Proposal or poll result
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct VetoCast {
+    pub queued_trade: Pubkey,
+    pub holder: Pubkey,
+    pub weight: u64,           // Including `delegated_weight`
+    pub delegated_weight: u64, // Shares vetoed for holders delegating to `holder`
+    pub veto_shares: u64,      // All vetoes so far
+    pub vetoed: bool,     // Whether this veto cancelled the trade
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesAccrued {
+    pub fund: Pubkey,
+    pub manager: Pubkey,
+    pub fee_shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesClaimed {
+    pub fee_vault: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StatusChanged {
+    pub fund: Pubkey,
+    pub from: FundStatus,
+    pub to: FundStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundsDrained {
+    pub fund: Pubkey,
+    pub destination: Pubkey,
+    pub lamports: u64,
+    pub token_mint: Pubkey,
+    pub tokens: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FundRolesUpdated {
+    pub fund: Pubkey,
+    pub owner: Pubkey,
+    pub pending_owner: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub traders: Vec<Pubkey>,
+}
+
+#[event]
+pub struct AllowlistProposed {
+    pub fund: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub apply_after: i64,
+}
+
+#[event]
+pub struct AllowlistApplied {
+    pub fund: Pubkey,
+    pub mints: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolConfigUpdated {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub treasury: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub fee_limits: FeeLimits,
+    pub poll_oracle: Pubkey,
+}
+
+#[event]
+pub struct MultisigUpdated {
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct ActionProposed {
+    pub pending_action: Pubkey,
+    pub id: u64,
+    pub action: PrivilegedAction,
+    pub proposer: Pubkey,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct ActionApproved {
+    pub pending_action: Pubkey,
+    pub approver: Pubkey,
+    pub approvals: u8, // From current signers, including this one
+    pub threshold: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ActionExecuted {
+    pub pending_action: Pubkey,
+    pub id: u64,
+    pub action: PrivilegedAction,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PriceFeedRegistered {
+    pub mint: Pubkey,
+    pub feed: Pubkey,
+    pub max_staleness: i64,
+    pub max_confidence_bps: u16,
+}
+
+#[event]
+pub struct FundManagerRegistered {
+    pub hedge_fund_owner: Pubkey,
+    pub hedge_fund: Pubkey,
+}
@@ -0,0 +1,142 @@
+use anchor_lang::prelude::*;
+
+use crate::config::{ProtocolConfig, PROTOCOL_CONFIG_SEED};
+use crate::events::FeesClaimed;
+use crate::FundError;
+
+pub const MANAGER_FEE_VAULT_SEED: &[u8] = b"manager_fees";
+pub const PROTOCOL_FEE_VAULT_SEED: &[u8] = b"protocol_fees";
+
+/// Lamports owed to one fee recipient, held in the account itself. A fund manager's vault
+/// is at `[MANAGER_FEE_VAULT_SEED, manager]`, the protocol's single vault at
+/// `[PROTOCOL_FEE_VAULT_SEED]`.
+#[account]
+pub struct FeeVault {
+    pub recipient: Pubkey, // Only set for manager vaults; the protocol vault pays `fee_recipient`
+    pub total_accrued: u64,
+    pub total_claimed: u64,
+    pub initialized: bool, // The protocol vault's recipient stays default, and 0 is a valid bump
+    pub bump: u8,
+}
+
+impl FeeVault {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 1 + 1;
+
+    /// Binds a freshly created vault to its recipient. No-op once initialized.
+    pub fn init_if_new(&mut self, recipient: Pubkey, bump: u8) {
+        if !self.initialized {
+            self.recipient = recipient;
+            self.initialized = true;
+            self.bump = bump;
+        }
+    }
+
+    /// Records `lamports` that have just been paid into the vault.
+    pub fn record_fee(&mut self, lamports: u64) -> Result<()> {
+        self.total_accrued = self.total_accrued.checked_add(lamports).ok_or(FundError::OverflowError)?;
+        Ok(())
+    }
+
+    pub fn unclaimed(&self) -> u64 {
+        self.total_accrued.saturating_sub(self.total_claimed)
+    }
+}
+
+pub fn process_claim_manager_fees(ctx: Context<ClaimManagerFees>) -> Result<()> {
+    let amount = claim(&mut ctx.accounts.fee_vault, &ctx.accounts.manager.to_account_info())?;
+    emit_cpi!(FeesClaimed {
+        fee_vault: ctx.accounts.fee_vault.key(),
+        recipient: ctx.accounts.manager.key(),
+        amount,
+        total_claimed: ctx.accounts.fee_vault.total_claimed,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn process_claim_protocol_fees(ctx: Context<ClaimProtocolFees>) -> Result<()> {
+    let amount = claim(&mut ctx.accounts.fee_vault, &ctx.accounts.fee_recipient.to_account_info())?;
+    emit_cpi!(FeesClaimed {
+        fee_vault: ctx.accounts.fee_vault.key(),
+        recipient: ctx.accounts.fee_recipient.key(),
+        amount,
+        total_claimed: ctx.accounts.fee_vault.total_claimed,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Pays everything unclaimed out to `to`. The vault is owned by this program, so its
+/// lamports are moved directly rather than through the system program.
+fn claim<'info>(fee_vault: &mut Account<'info, FeeVault>, to: &AccountInfo<'info>) -> Result<u64> {
+    let amount = fee_vault.unclaimed();
+    if amount == 0 {
+        return err!(FundError::NothingToClaim);
+    }
+
+    let vault_info = fee_vault.to_account_info();
+    let reserve = Rent::get()?.minimum_balance(vault_info.data_len());
+    if vault_info.lamports().saturating_sub(reserve) < amount {
+        msg!("Fee vault holds {} lamports, claim needs {}", vault_info.lamports(), amount);
+        return err!(FundError::InsufficientFunds);
+    }
+    **vault_info.try_borrow_mut_lamports()? -= amount;
+    **to.try_borrow_mut_lamports()? += amount;
+
+    fee_vault.total_claimed = fee_vault.total_claimed.checked_add(amount).ok_or(FundError::OverflowError)?;
+    msg!("Claimed {} lamports of fees, {} claimed in total", amount, fee_vault.total_claimed);
+    Ok(amount)
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimManagerFees<'info> {
+    #[account(
+        mut,
+        seeds = [MANAGER_FEE_VAULT_SEED, manager.key().as_ref()],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+
+    #[account(mut)]
+    pub manager: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimProtocolFees<'info> {
+    #[account(
+        mut,
+        seeds = [PROTOCOL_FEE_VAULT_SEED],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+
+    #[account(seeds = [PROTOCOL_CONFIG_SEED], bump = protocol_config.bump, has_one = fee_recipient @ FundError::Unauthorized)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(mut)]
+    pub fee_recipient: Signer<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vault_binds_once_even_with_a_zero_bump() {
+        let mut vault = FeeVault {
+            recipient: Pubkey::default(),
+            total_accrued: 0,
+            total_claimed: 0,
+            initialized: false,
+            bump: 0,
+        };
+        let manager = Pubkey::new_unique();
+        vault.init_if_new(manager, 0);
+        vault.init_if_new(Pubkey::new_unique(), 7);
+        assert_eq!(vault.recipient, manager);
+        assert_eq!(vault.bump, 0);
+        assert_eq!(vault.try_to_vec().unwrap().len() + 8, FeeVault::LEN);
+    }
+}
@@ -0,0 +1,342 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount};
+
+use crate::events::FeesAccrued;
+use crate::nav::{circulating_shares, pro_rata};
+use crate::{FundDetails, FundError};
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+// NAV per share is kept as lamports per share scaled by this; shares launch at 1 lamport each
+pub const NAV_PER_SHARE_SCALE: u64 = 1_000_000_000;
+pub const SECONDS_PER_YEAR: u64 = 60 * 60 * 24 * 365;
+
+/// Fees a fund charges, chosen by its creator at `create_fund` within the protocol's
+/// `FeeLimits`. `manager_share_bps` is the manager's cut of each deposit and redemption fee,
+/// the rest goes to the protocol.
+///
+/// The performance fee is taken as newly minted shares on NAV-per-share gains above the
+/// fund's high-water mark raised by `hurdle_bps`, at most once per `crystallization_period`.
+/// The management fee is an annual rate on the whole fund, accrued per second and also
+/// paid in newly minted shares.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeSchedule {
+    pub deposit_fee_bps: u16,
+    pub redemption_fee_bps: u16,
+    pub manager_share_bps: u16,
+    pub performance_fee_bps: u16,
+    pub hurdle_bps: u16,
+    pub crystallization_period: i64, // Seconds
+    pub management_fee_bps: u16,     // Per year
+}
+
+/// Upper bounds on every `FeeSchedule` field, kept in `ProtocolConfig`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeLimits {
+    pub max_deposit_fee_bps: u16,
+    pub max_redemption_fee_bps: u16,
+    pub max_manager_share_bps: u16,
+    pub max_performance_fee_bps: u16,
+    pub max_management_fee_bps: u16,
+}
+
+/// How a fee-bearing amount is divided up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeSplit {
+    pub net: u64,
+    pub manager_fee: u64,
+    pub protocol_fee: u64,
+}
+
+impl FeeSchedule {
+    pub const LEN: usize = 2 * 5 + 8 + 2;
+
+    pub fn validate(&self, limits: &FeeLimits) -> Result<()> {
+        if self.deposit_fee_bps > limits.max_deposit_fee_bps
+            || self.redemption_fee_bps > limits.max_redemption_fee_bps
+            || self.manager_share_bps > limits.max_manager_share_bps
+            || self.performance_fee_bps > limits.max_performance_fee_bps
+            || self.management_fee_bps > limits.max_management_fee_bps
+        {
+            msg!("Fee schedule {:?} exceeds protocol limits {:?}", self, limits);
+            return err!(FundError::FeeTooHigh);
+        }
+        if self.crystallization_period < 0 {
+            return err!(FundError::InvalidFeeSchedule);
+        }
+        Ok(())
+    }
+
+    pub fn deposit_fees(&self, amount: u64) -> Result<FeeSplit> {
+        self.split(amount, self.deposit_fee_bps)
+    }
+
+    pub fn redemption_fees(&self, amount: u64) -> Result<FeeSplit> {
+        self.split(amount, self.redemption_fee_bps)
+    }
+
+    fn split(&self, amount: u64, fee_bps: u16) -> Result<FeeSplit> {
+        let total_fee = pro_rata(amount, fee_bps as u64, BPS_DENOMINATOR).ok_or(FundError::OverflowError)?;
+        let manager_fee =
+            pro_rata(total_fee, self.manager_share_bps as u64, BPS_DENOMINATOR).ok_or(FundError::OverflowError)?;
+        Ok(FeeSplit {
+            net: amount.checked_sub(total_fee).ok_or(FundError::OverflowError)?,
+            manager_fee,
+            protocol_fee: total_fee.checked_sub(manager_fee).ok_or(FundError::OverflowError)?,
+        })
+    }
+
+    /// Performance fee owed by a fund worth `nav` with `outstanding` shares, as the number of
+    /// shares to mint to the manager, together with the high-water mark once they are minted.
+    ///
+    /// The fee is `performance_fee_bps` of the value gained above the hurdle, and the shares
+    /// are sized so that after dilution they are worth exactly that.
+    pub fn performance_fee_shares(&self, high_water_mark: u64, nav: u64, outstanding: u64) -> Result<(u64, u64)> {
+        if outstanding == 0 || self.performance_fee_bps == 0 {
+            return Ok((0, high_water_mark));
+        }
+        let per_share = nav_per_share(nav, outstanding)?;
+        let hurdle = pro_rata(high_water_mark, BPS_DENOMINATOR + self.hurdle_bps as u64, BPS_DENOMINATOR)
+            .ok_or(FundError::OverflowError)?;
+        if per_share <= hurdle {
+            return Ok((0, high_water_mark));
+        }
+
+        let gain = pro_rata(per_share - hurdle, outstanding, NAV_PER_SHARE_SCALE).ok_or(FundError::OverflowError)?;
+        let fee = pro_rata(gain, self.performance_fee_bps as u64, BPS_DENOMINATOR).ok_or(FundError::OverflowError)?;
+        if fee == 0 {
+            return Ok((0, high_water_mark));
+        }
+        let shares = pro_rata(fee, outstanding, nav - fee).ok_or(FundError::OverflowError)?;
+        let total_shares = outstanding.checked_add(shares).ok_or(FundError::OverflowError)?;
+        let new_mark = nav_per_share(nav, total_shares)?.max(high_water_mark);
+        Ok((shares, new_mark))
+    }
+
+    /// Shares to mint to the manager for `elapsed` seconds of management fee on a fund with
+    /// `outstanding` shares, sized so they make up exactly the accrued fraction of the fund.
+    pub fn management_fee_shares(&self, outstanding: u64, elapsed: u64) -> Result<u64> {
+        let accrued = (self.management_fee_bps as u128)
+            .checked_mul(elapsed as u128)
+            .ok_or(FundError::OverflowError)?;
+        let year = (BPS_DENOMINATOR as u128) * (SECONDS_PER_YEAR as u128);
+        if accrued == 0 || accrued >= year {
+            return Ok(0);
+        }
+        let shares = (outstanding as u128)
+            .checked_mul(accrued)
+            .ok_or(FundError::OverflowError)?
+            / (year - accrued);
+        u64::try_from(shares).map_err(|_| error!(FundError::OverflowError))
+    }
+}
+
+impl FeeLimits {
+    pub const LEN: usize = 2 * 5;
+
+    // What a freshly initialized protocol allows until the admin changes it
+    pub const DEFAULT: FeeLimits = FeeLimits {
+        max_deposit_fee_bps: 500,
+        max_redemption_fee_bps: 500,
+        max_manager_share_bps: 5_000,
+        max_performance_fee_bps: 3_000,
+        max_management_fee_bps: 300,
+    };
+
+    pub fn validate(&self) -> Result<()> {
+        let max = BPS_DENOMINATOR as u16;
+        if self.max_deposit_fee_bps > max
+            || self.max_redemption_fee_bps > max
+            || self.max_manager_share_bps > max
+            || self.max_performance_fee_bps > max
+            || self.max_management_fee_bps > max
+        {
+            return err!(FundError::InvalidFeeLimits);
+        }
+        Ok(())
+    }
+}
+
+pub fn nav_per_share(nav: u64, outstanding: u64) -> Result<u64> {
+    pro_rata(nav, NAV_PER_SHARE_SCALE, outstanding).ok_or_else(|| error!(FundError::OverflowError))
+}
+
+/// Mints fee shares from the fund's share mint, signed by the fund_details PDA.
+pub(crate) fn mint_fee_shares<'info>(
+    fund_details: &Account<'info, FundDetails>,
+    fund_details_seeds: &[&[u8]],
+    fund_token_mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    shares: u64,
+) -> Result<()> {
+    if shares == 0 {
+        return Ok(());
+    }
+    token::mint_to(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            MintTo {
+                mint: fund_token_mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: fund_details.to_account_info(),
+            },
+            &[fund_details_seeds],
+        ),
+        shares,
+    )
+}
+
+/// Permissionless: settles the management fee accrued since the fund was last touched.
+/// Deposits and redemptions settle it too, along with the performance fee.
+pub fn process_accrue_fees(ctx: Context<AccrueFees>, fund_id: String) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let outstanding_shares = circulating_shares(
+        ctx.accounts.fund_token_mint.supply,
+        ctx.accounts.fund_token_account.amount,
+    );
+    let fee_shares = ctx.accounts.fund_details.accrue_management_fee(outstanding_shares, now)?;
+
+    let authority = ctx.accounts.fund_details.authority;
+    let fee_recipient = ctx.accounts.fund_details.roles.fee_recipient;
+    let fund_details_bump = *ctx.bumps.get("fund_details").unwrap();
+    let fund_details_seeds: &[&[u8]] = &[
+        b"fund_details",
+        fund_id.as_bytes(),
+        authority.as_ref(),
+        &[fund_details_bump],
+    ];
+    mint_fee_shares(
+        &ctx.accounts.fund_details,
+        fund_details_seeds,
+        &ctx.accounts.fund_token_mint,
+        &ctx.accounts.manager_token_account,
+        &ctx.accounts.token_program,
+        fee_shares,
+    )?;
+
+    let fund_details = &mut ctx.accounts.fund_details;
+    fund_details.tokens_minted = fund_details.tokens_minted.checked_add(fee_shares)
+        .ok_or(FundError::OverflowError)?;
+
+    emit_cpi!(FeesAccrued {
+        fund: ctx.accounts.fund_details.key(),
+        manager: fee_recipient,
+        fee_shares,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(fund_id: String)]
+pub struct AccrueFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_details.authority.as_ref()],
+        bump,
+        has_one = fund_token_mint
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(mut)]
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = fund_details
+    )]
+    pub fund_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: The fund's fee recipient, only used as the owner of `manager_token_account`
+    #[account(address = fund_details.roles.fee_recipient)]
+    pub manager: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = manager,
+    )]
+    pub manager_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEDULE: FeeSchedule = FeeSchedule {
+        deposit_fee_bps: 100,
+        redemption_fee_bps: 50,
+        manager_share_bps: 2_000,
+        performance_fee_bps: 2_000,
+        hurdle_bps: 0,
+        crystallization_period: 0,
+        management_fee_bps: 200,
+    };
+
+    #[test]
+    fn fees_split_between_manager_and_protocol() {
+        let split = SCHEDULE.deposit_fees(1_000_000).unwrap();
+        assert_eq!(split.net, 990_000);
+        assert_eq!(split.manager_fee, 2_000);
+        assert_eq!(split.protocol_fee, 8_000);
+
+        let split = SCHEDULE.redemption_fees(1_000_000).unwrap();
+        assert_eq!(split.net + split.manager_fee + split.protocol_fee, 1_000_000);
+        assert_eq!(split.manager_fee, 1_000);
+    }
+
+    #[test]
+    fn schedule_must_fit_protocol_limits() {
+        assert!(SCHEDULE.validate(&FeeLimits::DEFAULT).is_ok());
+
+        let greedy = FeeSchedule { deposit_fee_bps: 501, ..SCHEDULE };
+        assert!(greedy.validate(&FeeLimits::DEFAULT).is_err());
+
+        let limits = FeeLimits { max_manager_share_bps: 10_001, ..FeeLimits::DEFAULT };
+        assert!(limits.validate().is_err());
+    }
+
+    #[test]
+    fn performance_fee_only_above_high_water_mark() {
+        let mark = NAV_PER_SHARE_SCALE;
+        // Flat or losing funds pay nothing and keep their mark
+        assert_eq!(SCHEDULE.performance_fee_shares(mark, 1_000, 1_000).unwrap(), (0, mark));
+        assert_eq!(SCHEDULE.performance_fee_shares(mark, 900, 1_000).unwrap(), (0, mark));
+
+        // 50% gain on 1000 shares: fee is 20% of 500 = 100 lamports of value
+        let (shares, new_mark) = SCHEDULE.performance_fee_shares(mark, 1_500, 1_000).unwrap();
+        assert_eq!(shares, 71);
+        let manager_value = 1_500 * shares / (1_000 + shares);
+        assert!((99..=100).contains(&manager_value));
+        assert_eq!(new_mark, nav_per_share(1_500, 1_071).unwrap());
+
+        // A hurdle of 60% swallows the whole 50% gain
+        let hurdled = FeeSchedule { hurdle_bps: 6_000, ..SCHEDULE };
+        assert_eq!(hurdled.performance_fee_shares(mark, 1_500, 1_000).unwrap(), (0, mark));
+    }
+
+    #[test]
+    fn management_fee_accrues_with_time() {
+        let outstanding = 1_000_000_000;
+        assert_eq!(SCHEDULE.management_fee_shares(outstanding, 0).unwrap(), 0);
+
+        // A full year at 2% leaves the manager with 2% of the enlarged share count
+        let shares = SCHEDULE.management_fee_shares(outstanding, SECONDS_PER_YEAR).unwrap();
+        assert!((199..=200).contains(&(shares * 10_000 / (outstanding + shares))));
+
+        // Two half years charge the same as one full year, up to compounding
+        let half = SCHEDULE.management_fee_shares(outstanding, SECONDS_PER_YEAR / 2).unwrap();
+        let second_half = SCHEDULE.management_fee_shares(outstanding + half, SECONDS_PER_YEAR / 2).unwrap();
+        assert!((half + second_half).abs_diff(shares) < shares / 100);
+    }
+}
@@ -0,0 +1,387 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::delegation::record_delegated_votes;
+use crate::events::{ProposalCreated, VoteCast};
+use crate::fees::BPS_DENOMINATOR;
+use crate::nav::{circulating_shares, pro_rata};
+use crate::position::{InvestorPosition, POSITION_SEED};
+use crate::{FundDetails, FundError};
+
+pub const PROPOSAL_SEED: &[u8] = b"proposal";
+pub const VOTE_SEED: &[u8] = b"vote";
+// An approved proposal has to be executed within this long after voting closes
+pub const PROPOSAL_EXECUTION_WINDOW_SECONDS: i64 = 60 * 60 * 24 * 3;
+pub const MAX_TRADE_DELAY_SECONDS: i64 = 60 * 60 * 24 * 7;
+
+/// How a fund's shareholders decide on trades, chosen at `create_fund`.
+///
+/// A proposal passes when the shares that voted reach `quorum_bps` of the shares outstanding
+/// when it was created, and the votes in favour reach `approval_threshold_bps` of those cast.
+/// Approved swaps are then queued for `trade_delay`, during which holders of
+/// `veto_threshold_bps` of the shares can cancel them.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GovernanceConfig {
+    pub quorum_bps: u16,
+    pub approval_threshold_bps: u16,
+    pub voting_period: i64, // Seconds
+    pub trade_delay: i64,   // Seconds
+    pub veto_threshold_bps: u16,
+}
+
+impl GovernanceConfig {
+    pub const LEN: usize = 2 + 2 + 8 + 8 + 2;
+
+    pub fn validate(&self) -> Result<()> {
+        let max = BPS_DENOMINATOR as u16;
+        if self.quorum_bps > max
+            || self.approval_threshold_bps == 0
+            || self.approval_threshold_bps > max
+            || self.voting_period <= 0
+            || self.trade_delay < 0
+            || self.trade_delay > MAX_TRADE_DELAY_SECONDS
+            || self.veto_threshold_bps == 0
+            || self.veto_threshold_bps > max
+        {
+            return err!(FundError::InvalidGovernanceConfig);
+        }
+        Ok(())
+    }
+}
+
+/// A swap put to the fund's shareholders, at `[PROPOSAL_SEED, fund, id]`.
+#[account]
+pub struct Proposal {
+    pub fund: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub votes_for: u64,
+    pub votes_against: u64,
+    pub eligible_shares: u64, // Shares outstanding at creation, the base for quorum
+    pub quorum_bps: u16,
+    pub approval_threshold_bps: u16,
+    pub voting_ends_at: i64,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub const LEN: usize = 8 + 32 + 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 2 + 2 + 8 + 1 + 1;
+
+    pub fn voting_open(&self, now: i64) -> bool {
+        now < self.voting_ends_at
+    }
+
+    /// Whether the vote has closed with quorum and enough support.
+    pub fn passed(&self, now: i64) -> bool {
+        if self.voting_open(now) {
+            return false;
+        }
+        let cast = self.votes_for.saturating_add(self.votes_against);
+        let quorum = pro_rata(self.eligible_shares, self.quorum_bps as u64, BPS_DENOMINATOR).unwrap_or(u64::MAX);
+        let needed = pro_rata(cast, self.approval_threshold_bps as u64, BPS_DENOMINATOR).unwrap_or(u64::MAX);
+        cast > 0 && cast >= quorum && self.votes_for >= needed
+    }
+
+    /// Checks the proposal authorizes a swap of `amount` from `input_mint` into `output_mint`
+    /// at `now`, and marks it used.
+    pub fn execute(
+        &mut self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        min_amount_out: u64,
+        now: i64,
+    ) -> Result<()> {
+        if self.executed {
+            return err!(FundError::ProposalAlreadyExecuted);
+        }
+        if !self.passed(now) {
+            return err!(FundError::ProposalNotApproved);
+        }
+        if now > self.voting_ends_at.saturating_add(PROPOSAL_EXECUTION_WINDOW_SECONDS) {
+            return err!(FundError::ProposalExpired);
+        }
+        if *input_mint != self.input_mint
+            || *output_mint != self.output_mint
+            || amount != self.amount
+            || min_amount_out < self.min_amount_out
+        {
+            msg!(
+                "Proposal approved {} {} -> {} (min {})",
+                self.amount,
+                self.input_mint,
+                self.output_mint,
+                self.min_amount_out
+            );
+            return err!(FundError::ProposalMismatch);
+        }
+        self.executed = true;
+        Ok(())
+    }
+}
+
+/// One holder's vote on one proposal, at `[VOTE_SEED, proposal, voter]`. Its existence is
+/// what stops the same holder's shares counting twice, directly or through a delegate.
+#[account]
+pub struct VoteRecord {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,   // Holder whose shares were counted
+    pub cast_by: Pubkey, // The holder, or the delegate that voted for them
+    pub weight: u64,
+    pub in_favor: bool,
+    pub bump: u8,
+}
+
+impl VoteRecord {
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 1 + 1;
+}
+
+/// Puts a swap to a vote. Only the fund's traders propose.
+pub fn process_create_proposal(
+    ctx: Context<CreateProposal>,
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    if amount == 0 || input_mint == output_mint {
+        return err!(FundError::InvalidProposal);
+    }
+    let now = Clock::get()?.unix_timestamp;
+    let fund_key = ctx.accounts.fund_details.key();
+    let governance = ctx.accounts.fund_details.governance;
+
+    let proposal = &mut ctx.accounts.proposal;
+    proposal.fund = fund_key;
+    proposal.id = ctx.accounts.fund_details.proposal_count;
+    proposal.proposer = ctx.accounts.proposer.key();
+    proposal.input_mint = input_mint;
+    proposal.output_mint = output_mint;
+    proposal.amount = amount;
+    proposal.min_amount_out = min_amount_out;
+    proposal.votes_for = 0;
+    proposal.votes_against = 0;
+    proposal.eligible_shares = circulating_shares(
+        ctx.accounts.fund_token_mint.supply,
+        ctx.accounts.fund_token_account.amount,
+    );
+    proposal.quorum_bps = governance.quorum_bps;
+    proposal.approval_threshold_bps = governance.approval_threshold_bps;
+    proposal.voting_ends_at = now.checked_add(governance.voting_period).ok_or(FundError::OverflowError)?;
+    proposal.executed = false;
+    proposal.bump = *ctx.bumps.get("proposal").unwrap();
+
+    let fund_details = &mut ctx.accounts.fund_details;
+    fund_details.proposal_count = fund_details.proposal_count.checked_add(1).ok_or(FundError::OverflowError)?;
+
+    msg!("Proposal {} open for votes until {}", proposal.id, proposal.voting_ends_at);
+    emit_cpi!(ProposalCreated {
+        fund: fund_key,
+        proposal: ctx.accounts.proposal.key(),
+        id: ctx.accounts.proposal.id,
+        input_mint,
+        output_mint,
+        amount,
+        min_amount_out,
+        voting_ends_at: ctx.accounts.proposal.voting_ends_at,
+    });
+    Ok(())
+}
+
+/// Votes with the voter's shares, plus those of every holder delegating to the voter whose
+/// accounts are passed in `remaining_accounts` (see `record_delegated_votes`). Shares move
+/// freely as SPL tokens, so only those both recorded on a holder's position and held in
+/// their wallet count; a transfer to a fresh wallet cannot be used to vote the same shares
+/// twice.
+pub fn process_cast_vote<'info>(ctx: Context<'_, '_, '_, 'info, CastVote<'info>>, in_favor: bool) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    if !ctx.accounts.proposal.voting_open(now) {
+        return err!(FundError::VotingClosed);
+    }
+
+    // A delegate without shares of their own passes neither account
+    let own_weight = match (&ctx.accounts.position, &ctx.accounts.voter_token_account) {
+        (Some(position), Some(token_account)) => position.shares.min(token_account.amount),
+        _ => 0,
+    };
+    let delegated_weight = record_delegated_votes(
+        ctx.program_id,
+        &ctx.accounts.fund_details,
+        &ctx.accounts.fund_details.key(),
+        &ctx.accounts.proposal.key(),
+        &ctx.accounts.voter.to_account_info(),
+        in_favor,
+        ctx.remaining_accounts,
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+    let weight = own_weight.checked_add(delegated_weight).ok_or(FundError::OverflowError)?;
+    if weight == 0 {
+        return err!(FundError::NoVotingPower);
+    }
+
+    let proposal = &mut ctx.accounts.proposal;
+    if in_favor {
+        proposal.votes_for = proposal.votes_for.checked_add(weight).ok_or(FundError::OverflowError)?;
+    } else {
+        proposal.votes_against = proposal.votes_against.checked_add(weight).ok_or(FundError::OverflowError)?;
+    }
+
+    let vote = &mut ctx.accounts.vote_record;
+    vote.proposal = proposal.key();
+    vote.voter = ctx.accounts.voter.key();
+    vote.cast_by = ctx.accounts.voter.key();
+    vote.weight = own_weight;
+    vote.in_favor = in_favor;
+    vote.bump = *ctx.bumps.get("vote_record").unwrap();
+
+    msg!(
+        "Voted {} with {} shares ({} delegated): {} for, {} against",
+        in_favor,
+        weight,
+        delegated_weight,
+        proposal.votes_for,
+        proposal.votes_against
+    );
+    emit_cpi!(VoteCast {
+        proposal: ctx.accounts.proposal.key(),
+        voter: ctx.accounts.voter.key(),
+        weight,
+        delegated_weight,
+        in_favor,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(
+        mut,
+        has_one = fund_token_mint,
+        constraint = fund_details.roles.is_trader(&proposer.key()) @ FundError::UnauthorizedTrader,
+        constraint = fund_details.effective_status(Clock::get()?.unix_timestamp).allows_trading() @ FundError::InvalidFundStatus
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::LEN,
+        seeds = [PROPOSAL_SEED, fund_details.key().as_ref(), &fund_details.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = fund_details
+    )]
+    pub fund_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CastVote<'info> {
+    #[account(has_one = fund_token_mint)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        mut,
+        seeds = [PROPOSAL_SEED, fund_details.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    #[account(
+        init,
+        payer = voter,
+        space = VoteRecord::LEN,
+        seeds = [VOTE_SEED, proposal.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub vote_record: Account<'info, VoteRecord>,
+
+    #[account(
+        seeds = [POSITION_SEED, fund_details.key().as_ref(), voter.key().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Option<Account<'info, InvestorPosition>>,
+
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = voter
+    )]
+    pub voter_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub voter: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal(votes_for: u64, votes_against: u64) -> Proposal {
+        Proposal {
+            fund: Pubkey::default(),
+            id: 0,
+            proposer: Pubkey::default(),
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount: 100,
+            min_amount_out: 90,
+            votes_for,
+            votes_against,
+            eligible_shares: 1_000,
+            quorum_bps: 2_000,
+            approval_threshold_bps: 5_000,
+            voting_ends_at: 100,
+            executed: false,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn proposals_need_quorum_and_majority() {
+        // Still voting
+        assert!(!proposal(900, 0).passed(99));
+        // Quorum is 200 of 1000 shares
+        assert!(!proposal(150, 0).passed(100));
+        assert!(proposal(200, 0).passed(100));
+        // Half the votes cast are needed in favour
+        assert!(proposal(150, 150).passed(100));
+        assert!(!proposal(149, 151).passed(100));
+    }
+
+    #[test]
+    fn proposals_execute_once_and_only_as_approved() {
+        let mut p = proposal(600, 0);
+        let (input, output) = (p.input_mint, p.output_mint);
+        assert!(p.execute(&input, &output, 100, 80, 101).is_err());
+        assert!(p.execute(&input, &output, 101, 90, 101).is_err());
+        assert!(p.execute(&output, &input, 100, 90, 101).is_err());
+        assert!(p.execute(&input, &output, 100, 95, 101).is_ok());
+        assert!(p.execute(&input, &output, 100, 95, 101).is_err());
+
+        let mut stale = proposal(600, 0);
+        let late = 100 + PROPOSAL_EXECUTION_WINDOW_SECONDS + 1;
+        assert!(stale.execute(&input, &output, 100, 90, late).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use anchor_lang::prelude::*;
+
+use crate::swap::SwapAmounts;
+use crate::FundError;
+
+pub const TRADE_LEDGER_SEED: &[u8] = b"trade_ledger";
+pub const TRADE_LEDGER_CAPACITY: usize = 32;
+
+/// One swap the fund made, with amounts measured on the fund's own accounts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TradeRecord {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+    pub proposal: Pubkey, // Pubkey::default() when the trade was not authorized by a proposal
+}
+
+impl TradeRecord {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 8 + 32;
+
+    pub fn new(input_mint: Pubkey, output_mint: Pubkey, swapped: SwapAmounts, proposal: Pubkey) -> Result<Self> {
+        let clock = Clock::get()?;
+        Ok(TradeRecord {
+            input_mint,
+            output_mint,
+            amount_in: swapped.amount_in,
+            amount_out: swapped.amount_out,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+            proposal,
+        })
+    }
+}
+
+/// The fund's most recent trades, at `[TRADE_LEDGER_SEED, fund]`. Once full, each new trade
+/// overwrites the oldest one; `total_trades` keeps counting so gaps are detectable.
+#[account]
+pub struct TradeLedger {
+    pub fund: Pubkey,
+    pub total_trades: u64,
+    pub next_index: u32, // Slot in `entries` the next trade is written to
+    pub entries: Vec<TradeRecord>,
+    pub bump: u8,
+}
+
+impl TradeLedger {
+    pub const LEN: usize = 8 + 32 + 8 + 4 + 4 + TradeRecord::LEN * TRADE_LEDGER_CAPACITY + 1;
+
+    pub fn initialize(&mut self, fund: Pubkey, bump: u8) {
+        self.fund = fund;
+        self.total_trades = 0;
+        self.next_index = 0;
+        self.entries = Vec::new();
+        self.bump = bump;
+    }
+
+    pub fn record(&mut self, entry: TradeRecord) -> Result<()> {
+        let index = self.next_index as usize;
+        if self.entries.len() < TRADE_LEDGER_CAPACITY {
+            self.entries.push(entry);
+        } else {
+            self.entries[index] = entry;
+        }
+        self.next_index = ((index + 1) % TRADE_LEDGER_CAPACITY) as u32;
+        self.total_trades = self.total_trades.checked_add(1).ok_or(FundError::OverflowError)?;
+        Ok(())
+    }
+
+    /// Entries from oldest to newest.
+    pub fn chronological(&self) -> impl Iterator<Item = &TradeRecord> {
+        let split = if self.entries.len() < TRADE_LEDGER_CAPACITY { 0 } else { self.next_index as usize };
+        self.entries[split..].iter().chain(self.entries[..split].iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(n: u64) -> TradeRecord {
+        TradeRecord {
+            input_mint: Pubkey::default(),
+            output_mint: Pubkey::default(),
+            amount_in: n,
+            amount_out: n,
+            slot: n,
+            timestamp: n as i64,
+            proposal: Pubkey::default(),
+        }
+    }
+
+    fn empty_ledger() -> TradeLedger {
+        TradeLedger {
+            fund: Pubkey::default(),
+            total_trades: 0,
+            next_index: 0,
+            entries: Vec::new(),
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn ledger_keeps_the_latest_trades() {
+        let mut ledger = empty_ledger();
+        for n in 0..3 {
+            ledger.record(entry(n)).unwrap();
+        }
+        let slots: Vec<u64> = ledger.chronological().map(|e| e.slot).collect();
+        assert_eq!(slots, vec![0, 1, 2]);
+
+        let total = TRADE_LEDGER_CAPACITY as u64 + 5;
+        for n in 3..total {
+            ledger.record(entry(n)).unwrap();
+        }
+        assert_eq!(ledger.entries.len(), TRADE_LEDGER_CAPACITY);
+        assert_eq!(ledger.total_trades, total);
+        let slots: Vec<u64> = ledger.chronological().map(|e| e.slot).collect();
+        assert_eq!(slots, (5..total).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn full_ledger_fits_its_account() {
+        let mut ledger = empty_ledger();
+        for n in 0..TRADE_LEDGER_CAPACITY as u64 {
+            ledger.record(entry(n)).unwrap();
+        }
+        assert_eq!(ledger.try_to_vec().unwrap().len() + 8, TradeLedger::LEN);
+    }
+}
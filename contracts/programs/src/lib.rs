@@ -1,13 +1,52 @@
-use std::str::FromStr;
+// Every instruction returns anchor_lang::error::Error, which is over clippy's size limit for Err
+#![allow(clippy::result_large_err)]
 
-use anchor_lang::system_program::{transfer, Transfer};
+use anchor_lang::system_program::{transfer, Transfer as SystemTransfer};
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{Mint, MintTo};
+mod allowlist;
+mod config;
+mod delegation;
 mod deposits;
+mod events;
+mod fee_vault;
+mod fees;
+mod governance;
+mod ledger;
+mod lifecycle;
+mod liquidation;
+mod multisig;
+mod nav;
+mod oracle;
+mod poll;
+mod position;
+mod queue;
+mod rebalance;
 mod redeem;
+mod refund;
+mod roles;
 mod swap;
 
+pub use allowlist::*;
+pub use config::*;
+pub use delegation::*;
 pub use deposits::*;
+pub use events::*;
+pub use fee_vault::*;
+pub use fees::*;
+pub use governance::*;
+pub use ledger::*;
+pub use lifecycle::*;
+pub use liquidation::*;
+pub use multisig::*;
+pub use oracle::*;
+pub use poll::*;
+pub use position::*;
+pub use queue::*;
+pub use rebalance::*;
+pub use redeem::*;
+pub use refund::*;
+pub use roles::*;
 use solana_program::system_program;
 pub use swap::ErrorCode;
 pub use swap::*;
@@ -18,11 +57,37 @@ pub const AUTHORITY_SEED: &[u8] = b"authority";
 pub const WSOL_SEED: &[u8] = b"wsol";
 pub const VAULT_SEED: &[u8] = b"fund_vault";
 pub const FUND_TOKEN_SEED: &[u8] = b"fund_token";
+// Most a trade may lose against the oracle value of the SOL it spends
+pub const TRADE_MAX_SLIPPAGE_BPS: u64 = 300;
 
 #[program]
 pub mod first {
     use super::*;
-    pub use deposits::{process_deposit, DepositLiquidity, UserDeposit, LAMPORTS_PER_SOL};
+    pub use allowlist::{process_apply_allowlist, process_propose_allowlist, ApplyAllowlist, ProposeAllowlist};
+    pub use config::{
+        process_accept_protocol_admin, process_initialize_protocol, process_propose_protocol_admin,
+        process_set_fee_limits, process_set_poll_oracle, process_set_protocol_roles, AcceptProtocolAdmin, InitializeProtocol, UpdateProtocolConfig,
+    };
+    pub use delegation::{process_delegate_votes, process_revoke_delegation, DelegateVotes, RevokeDelegation};
+    pub use deposits::{process_deposit, DepositLiquidity, LAMPORTS_PER_SOL};
+    pub use fee_vault::{process_claim_manager_fees, process_claim_protocol_fees, ClaimManagerFees, ClaimProtocolFees};
+    pub use fees::{process_accrue_fees, AccrueFees};
+    pub use governance::{process_cast_vote, process_create_proposal, CastVote, CreateProposal};
+    pub use lifecycle::{process_crank_lifecycle, CrankLifecycle};
+    pub use liquidation::{process_liquidate_holding, LiquidateHolding};
+    pub use multisig::{
+        process_approve_action, process_initialize_multisig, process_propose_action, process_update_multisig, ApproveAction,
+        InitializeMultisig, ProposeAction, UpdateMultisig,
+    };
+    pub use oracle::{process_register_price_feed, RegisterPriceFeed};
+    pub use poll::{process_record_poll_result, RecordPollResult};
+    pub use queue::{process_queue_trade, process_veto_trade, QueueTrade, VetoTrade};
+    pub use rebalance::{process_rebalance, Rebalance};
+    pub use redeem::{process_redeem, RedeemLiquidity};
+    pub use refund::{process_claim_refund, ClaimRefund};
+    pub use roles::{
+        process_accept_fund_owner, process_propose_fund_owner, process_set_fund_roles, AcceptFundOwner, UpdateFundRoles,
+    };
     pub use swap::{sol_to_swap, swap_to_sol, SOLToSwap, SwapToSOL};
 
     pub fn create_fund_token_account(
@@ -31,8 +96,7 @@ pub mod first {
     ) -> Result<()> {
         msg!("Creating fund token account for fund_id: {}", fund_id);
 
-        let authority_bump = *ctx.bumps.get("program_authority").unwrap();
-        let authority_seeds = &[AUTHORITY_SEED, &[authority_bump]];
+        let fund_key = ctx.accounts.fund_details.key();
 
         let rent = Rent::get()?;
         let space = TokenAccount::LEN;
@@ -53,7 +117,7 @@ pub mod first {
             ],
             &[&[
                 FUND_TOKEN_SEED,
-                fund_id.as_bytes(),
+                fund_key.as_ref(),
                 ctx.accounts.usdc_mint.key().as_ref(),
                 &[*ctx.bumps.get("fund_token_account").unwrap()],
             ]],
@@ -69,7 +133,17 @@ pub mod first {
             },
         ))?;
 
+        ctx.accounts
+            .fund_details
+            .track_holding(ctx.accounts.usdc_mint.key())?;
+
         msg!("Fund token account created successfully");
+        emit_cpi!(HoldingAdded {
+            fund: fund_key,
+            mint: ctx.accounts.usdc_mint.key(),
+            token_account: ctx.accounts.fund_token_account.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
         Ok(())
     }
 
@@ -79,7 +153,6 @@ pub mod first {
         fund_id: String,
         fund_creator: Pubkey,
         manager_address: Pubkey,
-        tvl: u64,
     ) -> Result<()> {
         let tokens_to_transfer = amount;
         process_deposit(
@@ -88,37 +161,149 @@ pub mod first {
             fund_id,
             fund_creator,
             manager_address,
-            tvl,
         )
     }
 
-    // pub  fn redeem_liquidity(ctx: Context<RedeemLiquidity>,amount:u64,fund_id:String,fund_creator:Pubkey,manager_address:Pubkey) -> Result<()> {
-    //     process_redeem(ctx,amount,fund_id,fund_creator,manager_address)
-    // }
+    pub fn redeem_liquidity(
+        ctx: Context<RedeemLiquidity>,
+        amount: u64,
+        fund_id: String,
+        fund_creator: Pubkey,
+        manager_address: Pubkey,
+    ) -> Result<()> {
+        process_redeem(ctx, amount, fund_id, fund_creator, manager_address)
+    }
 
-    pub fn get_all_funds(ctx: Context<GetAllFunds>) -> Result<Vec<CompleteFundInfo>> {
+    pub fn claim_refund(
+        ctx: Context<ClaimRefund>,
+        _fund_id: String,
+        _fund_creator: Pubkey,
+    ) -> Result<()> {
+        process_claim_refund(ctx)
+    }
+
+    pub fn register_price_feed(
+        ctx: Context<RegisterPriceFeed>,
+        feed: Pubkey,
+        max_staleness: i64,
+        max_confidence_bps: u16,
+    ) -> Result<()> {
+        process_register_price_feed(ctx, feed, max_staleness, max_confidence_bps)
+    }
+
+    pub fn get_all_funds(_ctx: Context<GetAllFunds>) -> Result<Vec<CompleteFundInfo>> {
         // This will be handled client-side as we need to fetch all accounts
         Ok(Vec::new())
     }
 
+    pub fn initialize_protocol(ctx: Context<InitializeProtocol>, treasury: Pubkey, fee_recipient: Pubkey) -> Result<()> {
+        process_initialize_protocol(ctx, treasury, fee_recipient)
+    }
+
+    pub fn set_protocol_roles(ctx: Context<UpdateProtocolConfig>, treasury: Pubkey, fee_recipient: Pubkey) -> Result<()> {
+        process_set_protocol_roles(ctx, treasury, fee_recipient)
+    }
+
+    pub fn set_fee_limits(ctx: Context<UpdateProtocolConfig>, fee_limits: FeeLimits) -> Result<()> {
+        process_set_fee_limits(ctx, fee_limits)
+    }
+
+    pub fn set_poll_oracle(ctx: Context<UpdateProtocolConfig>, poll_oracle: Pubkey) -> Result<()> {
+        process_set_poll_oracle(ctx, poll_oracle)
+    }
+
+    pub fn propose_protocol_admin(ctx: Context<UpdateProtocolConfig>, new_admin: Pubkey) -> Result<()> {
+        process_propose_protocol_admin(ctx, new_admin)
+    }
+
+    pub fn accept_protocol_admin(ctx: Context<AcceptProtocolAdmin>) -> Result<()> {
+        process_accept_protocol_admin(ctx)
+    }
+
+    pub fn initialize_multisig(ctx: Context<InitializeMultisig>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        process_initialize_multisig(ctx, signers, threshold)
+    }
+
+    pub fn propose_action(ctx: Context<ProposeAction>, action: PrivilegedAction, expires_in: i64) -> Result<()> {
+        process_propose_action(ctx, action, expires_in)
+    }
+
+    pub fn approve_action(ctx: Context<ApproveAction>) -> Result<()> {
+        process_approve_action(ctx)
+    }
+
+    pub fn update_multisig(ctx: Context<UpdateMultisig>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        process_update_multisig(ctx, signers, threshold)
+    }
+
     pub fn initialize(ctx: Context<Initialize>, hedge_fund: Pubkey) -> Result<()> {
+        let executed = execute_approved(
+            &ctx.accounts.multisig,
+            &mut ctx.accounts.pending_action,
+            PrivilegedAction::RegisterFundManager { hedge_fund },
+        )?;
+        emit_cpi!(executed);
+
         ctx.accounts.hedge_fund_owner.hedge_fund = hedge_fund;
+        emit_cpi!(FundManagerRegistered {
+            hedge_fund_owner: ctx.accounts.hedge_fund_owner.key(),
+            hedge_fund,
+        });
         Ok(())
     }
 
-    pub fn trade(ctx: Context<Trade>, fund_id: String, amount: u64, data: Vec<u8>) -> Result<()> {
+    pub fn trade(
+        ctx: Context<Trade>,
+        fund_id: String,
+        amount: u64,
+        min_amount_out: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
         msg!("Trade instruction started for fund_id: {}", fund_id);
         msg!(
             "Fund total_deposit: {:?}",
             ctx.accounts.fund_details.total_deposit
         );
 
+        // The fund may only trade at prices the oracle vouches for on both sides, otherwise
+        // NAV breaks. The fill is checked against them once the swap has settled
+        let oracle = Oracle::load(
+            ctx.program_id,
+            &[
+                ctx.accounts.sol_price_feed.to_account_info(),
+                ctx.accounts.sol_feed.to_account_info(),
+                ctx.accounts.destination_price_feed.to_account_info(),
+                ctx.accounts.destination_feed.to_account_info(),
+            ],
+            Clock::get()?.unix_timestamp,
+        )?;
+        let sol_price = oracle.price_of(&ctx.accounts.sol_mint.key())?;
+        let destination_price = oracle.price_of(&ctx.accounts.destination_mint.key())?;
+        msg!(
+            "SOL price: {} x 10^{}, destination price: {} x 10^{}",
+            sol_price.price,
+            sol_price.expo,
+            destination_price.price,
+            destination_price.expo
+        );
+
+        // Only a queued swap whose delay passed without a veto can execute
+        ctx.accounts.queued_trade.execute(
+            &ctx.accounts.sol_mint.key(),
+            &ctx.accounts.destination_mint.key(),
+            amount,
+            min_amount_out,
+            Clock::get()?.unix_timestamp,
+        )?;
+        let authorization = ctx.accounts.queued_trade.authorization;
+        msg!("Executing queued trade {}", ctx.accounts.queued_trade.id);
+
         let fund_details = &mut ctx.accounts.fund_details;
 
         // Calculate required SOL
         let rent = Rent::get()?;
         let wsol_rent = rent.minimum_balance(TokenAccount::LEN);
-        let buffer = 5000; // Same buffer as in sol_to_swap for consistency
+        let buffer = SWAP_LAMPORTS_BUFFER; // Same buffer sol_to_swap moves out of the vault
         let total_required = amount + wsol_rent + buffer;
 
         // Get balances
@@ -180,19 +365,50 @@ pub mod first {
 
         // Execute the swap
         msg!("Calling sol_to_swap with amount: {}", amount);
-        let result = swap::sol_to_swap(sol_to_swap_ctx, fund_id, amount, data);
+        let result = swap::sol_to_swap(sol_to_swap_ctx, fund_id, amount, min_amount_out, data);
 
         // Log result status
         match &result {
             Ok(_) => msg!("Trade completed successfully"),
             Err(e) => msg!("Trade failed with error: {:?}", e),
         }
+        let swapped = result?;
+
+        // Holders approved the minimum out when the trade was queued, the oracle bounds the fill now
+        oracle.check_fill_for_lamports(
+            swapped.amount_in,
+            &ctx.accounts.destination_mint.key(),
+            swapped.amount_out,
+            TRADE_MAX_SLIPPAGE_BPS,
+        )?;
 
-        result
+        // The fund now holds the destination token, so it has to be counted in NAV
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .fund_details
+            .record_trade(ctx.accounts.destination_mint.key(), now)?;
+        ctx.accounts.trade_ledger.record(TradeRecord::new(
+            ctx.accounts.sol_mint.key(),
+            ctx.accounts.destination_mint.key(),
+            swapped,
+            authorization,
+        )?)?;
+
+        emit_cpi!(TradeExecuted {
+            fund: ctx.accounts.fund_details.key(),
+            trader: ctx.accounts.user_account.key(),
+            input_mint: ctx.accounts.sol_mint.key(),
+            output_mint: ctx.accounts.destination_mint.key(),
+            amount_in: swapped.amount_in,
+            amount_out: swapped.amount_out,
+            timestamp: now,
+        });
+        Ok(())
     }
 
 
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_fund(
         ctx: Context<CreateFund>,
         fund_id: String,
@@ -200,12 +416,33 @@ pub mod first {
         fund_name: String,
         description: String,
         invest_threshold: u64,
+        allowed_mints: Vec<Pubkey>,
+        fundraise_period: i64,
+        fee_schedule: FeeSchedule,
+        governance: GovernanceConfig,
     ) -> Result<()> {
+        if fundraise_period <= 0 || fundraise_period > THREE_MONTHS_SECONDS {
+            return err!(FundError::InvalidFundraisePeriod);
+        }
+        fee_schedule.validate(&ctx.accounts.protocol_config.fee_limits)?;
+        governance.validate()?;
+
+        let fund_key = ctx.accounts.fund_details.key();
+        ctx.accounts.asset_allowlist.initialize(
+            fund_key,
+            allowed_mints,
+            *ctx.bumps.get("asset_allowlist").unwrap(),
+        )?;
+        ctx.accounts
+            .trade_ledger
+            .initialize(fund_key, *ctx.bumps.get("trade_ledger").unwrap());
+
         let fund_details = &mut ctx.accounts.fund_details;
 
         fund_details.fund_token_mint = ctx.accounts.token_mint.key();
 
         fund_details.start_time = Clock::get()?.unix_timestamp;
+        fund_details.fundraise_deadline = fund_details.start_time + fundraise_period;
         fund_details.tokens_minted = amount;
         fund_details.total_deposit = 0;
         let fund_id_bytes = fund_id.as_bytes();
@@ -227,14 +464,21 @@ pub mod first {
         fund_details.description = desc_array;
 
         fund_details.invest_threshold = invest_threshold;
+        fund_details.fee_schedule = fee_schedule;
+        fund_details.high_water_mark = NAV_PER_SHARE_SCALE;
+        fund_details.last_crystallization = fund_details.start_time;
+        fund_details.last_fee_accrual = fund_details.start_time;
+        fund_details.governance = governance;
+        fund_details.proposal_count = 0;
+        fund_details.queued_trade_count = 0;
         fund_details.authority = ctx.accounts.user.key();
-        // Initialize status as active
-        fund_details.status = FUND_STATUS_ACTIVE;
+        fund_details.roles = FundRoles::new(ctx.accounts.user.key());
+        fund_details.status = FundStatus::Fundraising;
         // Then do the minting in a new scope
         let binding = ctx.accounts.user.key();
         let seeds = &[
             b"fund_details".as_ref(),
-            fund_id.as_bytes().as_ref(),
+            fund_id.as_bytes(),
             binding.as_ref(), // Add user's public key to seeds
             &[*ctx.bumps.get("fund_details").unwrap()],
         ];
@@ -254,24 +498,124 @@ pub mod first {
 
         fund_details.tokens_minted = amount;
 
+        emit_cpi!(FundCreated {
+            fund: fund_key,
+            authority: fund_details.authority,
+            fund_token_mint: fund_details.fund_token_mint,
+            initial_shares: amount,
+            invest_threshold,
+            fundraise_deadline: fund_details.fundraise_deadline,
+            fee_schedule,
+            governance,
+            allowed_mints: ctx.accounts.asset_allowlist.mints.clone(),
+            timestamp: fund_details.start_time,
+        });
         Ok(())
     }
 
-    pub fn get_user_deposits(
-        ctx: Context<GetUserDeposits>,
-        fund_id: String,
+    pub fn set_fund_roles(
+        ctx: Context<UpdateFundRoles>,
+        traders: Vec<Pubkey>,
+        fee_recipient: Pubkey,
+    ) -> Result<()> {
+        process_set_fund_roles(ctx, traders, fee_recipient)
+    }
+
+    pub fn propose_fund_owner(ctx: Context<UpdateFundRoles>, new_owner: Pubkey) -> Result<()> {
+        process_propose_fund_owner(ctx, new_owner)
+    }
+
+    pub fn accept_fund_owner(ctx: Context<AcceptFundOwner>) -> Result<()> {
+        process_accept_fund_owner(ctx)
+    }
+
+    pub fn propose_allowlist(
+        ctx: Context<ProposeAllowlist>,
+        _fund_id: String,
+        mints: Vec<Pubkey>,
+    ) -> Result<()> {
+        process_propose_allowlist(ctx, mints)
+    }
+
+    pub fn apply_allowlist(ctx: Context<ApplyAllowlist>) -> Result<()> {
+        process_apply_allowlist(ctx)
+    }
+
+    pub fn crank_lifecycle(ctx: Context<CrankLifecycle>) -> Result<()> {
+        process_crank_lifecycle(ctx)
+    }
+
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        process_create_proposal(ctx, input_mint, output_mint, amount, min_amount_out)
+    }
+
+    pub fn cast_vote<'info>(ctx: Context<'_, '_, '_, 'info, CastVote<'info>>, in_favor: bool) -> Result<()> {
+        process_cast_vote(ctx, in_favor)
+    }
+
+    pub fn delegate_votes(ctx: Context<DelegateVotes>, delegate: Pubkey) -> Result<()> {
+        process_delegate_votes(ctx, delegate)
+    }
+
+    pub fn revoke_delegation(ctx: Context<RevokeDelegation>) -> Result<()> {
+        process_revoke_delegation(ctx)
+    }
+
+    pub fn queue_trade(ctx: Context<QueueTrade>, amount: u64, min_amount_out: u64) -> Result<()> {
+        process_queue_trade(ctx, amount, min_amount_out)
+    }
+
+    pub fn veto_trade<'info>(ctx: Context<'_, '_, '_, 'info, VetoTrade<'info>>) -> Result<()> {
+        process_veto_trade(ctx)
+    }
+
+    pub fn accrue_fees(ctx: Context<AccrueFees>, fund_id: String) -> Result<()> {
+        process_accrue_fees(ctx, fund_id)
+    }
+
+    pub fn claim_manager_fees(ctx: Context<ClaimManagerFees>) -> Result<()> {
+        process_claim_manager_fees(ctx)
+    }
+
+    pub fn claim_protocol_fees(ctx: Context<ClaimProtocolFees>) -> Result<()> {
+        process_claim_protocol_fees(ctx)
+    }
+
+    pub fn liquidate_holding(ctx: Context<LiquidateHolding>, data: Vec<u8>) -> Result<()> {
+        process_liquidate_holding(ctx, data)
+    }
+
+    pub fn get_position(
+        ctx: Context<GetPosition>,
+        _fund_id: String,
         _fund_creator: Pubkey,
-    ) -> Result<Vec<UserDeposit>> {
-        let fund_details = &ctx.accounts.fund_details;
-        Ok(fund_details.user_deposits.clone())
+        _investor: Pubkey,
+    ) -> Result<InvestorPosition> {
+        Ok((*ctx.accounts.position).clone())
     }
 
     pub fn drain_all_funds(ctx: Context<DrainAllFunds>, fund_id: String) -> Result<()> {
-        msg!("Starting to drain all funds...");
+        msg!("Starting to drain all funds for fund_id: {}", fund_id);
+
+        let fund_key = ctx.accounts.fund_details.key();
+        let executed = execute_approved(
+            &ctx.accounts.multisig,
+            &mut ctx.accounts.pending_action,
+            PrivilegedAction::DrainFund { fund: fund_key },
+        )?;
+        emit_cpi!(executed);
+        let authority_bump = *ctx.bumps.get("program_authority").unwrap();
+        let authority_seeds = &[AUTHORITY_SEED, fund_key.as_ref(), &[authority_bump]];
 
         // 1. Drain SOL from fund vault
         let vault_bump = *ctx.bumps.get("fund_vault").unwrap();
-        let vault_seeds = &[VAULT_SEED, fund_id.as_bytes(), &[vault_bump]];
+        let vault_seeds = &[VAULT_SEED, fund_key.as_ref(), &[vault_bump]];
         let vault_balance = ctx.accounts.fund_vault.lamports();
 
         if vault_balance > 0 {
@@ -291,38 +635,26 @@ pub mod first {
             )?;
         }
 
-        // 2. Drain USDC from fund token account
-        let fund_token_bump = *ctx.bumps.get("fund_token_account").unwrap();
+        // 2. Drain USDC from fund token account, which the fund's authority owns
         let usdc_balance = ctx.accounts.fund_token_account.amount;
 
         if usdc_balance > 0 {
             msg!("Draining {} USDC tokens", usdc_balance);
-            // Fix the borrowing issue by storing the key in a variable
-            let usdc_mint_key = ctx.accounts.usdc_mint.key();
-            let token_seeds = &[
-                FUND_TOKEN_SEED,
-                fund_id.as_bytes(),
-                usdc_mint_key.as_ref(),
-                &[fund_token_bump],
-            ];
-
             token::transfer(
                 CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
                     token::Transfer {
                         from: ctx.accounts.fund_token_account.to_account_info(),
                         to: ctx.accounts.destination_token_account.to_account_info(),
-                        authority: ctx.accounts.fund_token_account.to_account_info(),
+                        authority: ctx.accounts.program_authority.to_account_info(),
                     },
-                    &[token_seeds],
+                    &[authority_seeds],
                 ),
                 usdc_balance,
             )?;
         }
 
         // 3. Drain SOL from program authority
-        let authority_bump = *ctx.bumps.get("program_authority").unwrap();
-        let authority_seeds = &[AUTHORITY_SEED, &[authority_bump]];
         let authority_balance = ctx.accounts.program_authority.lamports();
         let min_rent = Rent::get()?.minimum_balance(0);
         let transfer_amount = authority_balance.saturating_sub(min_rent);
@@ -335,7 +667,7 @@ pub mod first {
             transfer(
                 CpiContext::new_with_signer(
                     ctx.accounts.system_program.to_account_info(),
-                    Transfer {
+                    SystemTransfer {
                         from: ctx.accounts.program_authority.to_account_info(),
                         to: ctx.accounts.destination.to_account_info(),
                     },
@@ -346,6 +678,14 @@ pub mod first {
         }
 
         msg!("All funds drained successfully");
+        emit_cpi!(FundsDrained {
+            fund: fund_key,
+            destination: ctx.accounts.destination.key(),
+            lamports: vault_balance.saturating_add(transfer_amount),
+            token_mint: ctx.accounts.usdc_mint.key(),
+            tokens: usdc_balance,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
         Ok(())
     }
 
@@ -355,6 +695,7 @@ pub mod first {
         ctx: Context<UsdcToSolTrade>,
         fund_id: String,
         amount: u64,
+        min_amount_out: u64,
         data: Vec<u8>,
     ) -> Result<()> {
         msg!(
@@ -362,9 +703,17 @@ pub mod first {
             fund_id
         );
 
-        // Get authority bump for signing
-        let authority_bump = *ctx.bumps.get("program_authority").unwrap();
-        let authority_seeds = &[AUTHORITY_SEED, &[authority_bump]];
+        // Only a queued swap whose delay passed without a veto can execute
+        ctx.accounts.queued_trade.execute(
+            &ctx.accounts.usdc_mint.key(),
+            &ctx.accounts.sol_mint.key(),
+            amount,
+            min_amount_out,
+            Clock::get()?.unix_timestamp,
+        )?;
+        msg!("Executing queued trade {}", ctx.accounts.queued_trade.id);
+
+        let fund_key = ctx.accounts.fund_details.key();
 
         msg!("Transferring SOL from user to program authority for WSOL operations");
         let transfer_amount = 3000000; // 0.003 SOL for WSOL account creation and operations
@@ -373,7 +722,7 @@ pub mod first {
         transfer(
             CpiContext::new(
                 ctx.accounts.system_program.to_account_info(),
-                Transfer {
+                SystemTransfer {
                     from: ctx.accounts.user_account.to_account_info(),
                     to: ctx.accounts.program_authority.to_account_info(),
                 },
@@ -381,41 +730,16 @@ pub mod first {
             transfer_amount,
         )?;
 
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.fund_token_account.to_account_info(),
-                    to: ctx.accounts.destination_token_account.to_account_info(),
-                    authority: ctx.accounts.program_authority.to_account_info(),
-                },
-                &[authority_seeds],
-            ),
-            amount,
-        )?;
-
-        // Approve Jupiter program to spend USDC tokens
-        msg!("Approving Jupiter program to spend USDC tokens");
-        token::approve(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Approve {
-                    to: ctx.accounts.fund_token_account.to_account_info(),
-                    delegate: ctx.accounts.jupiter_program.to_account_info(),
-                    authority: ctx.accounts.program_authority.to_account_info(),
-                },
-                &[authority_seeds],
-            ),
-            amount,
-        )?;
-
+        // The authority signs the swap CPI itself, so Jupiter needs no delegation on the
+        // holding; one left behind would also fail the next swap out of it
         // Prepare context for swap
         let swap_to_sol_ctx = Context {
             program_id: ctx.program_id,
             accounts: &mut swap::SwapToSOL {
+                fund_details: ctx.accounts.fund_details.clone(),
                 program_authority: ctx.accounts.program_authority.clone(),
                 program_wsol_account: ctx.accounts.program_wsol_account.clone(),
-                user_account: ctx.accounts.user_account.clone(),
+                user_account: ctx.accounts.user_account.to_account_info(),
                 sol_mint: ctx.accounts.sol_mint.clone(),
                 user_token_account: ctx.accounts.fund_token_account.clone(),
                 fund_vault: ctx.accounts.fund_vault.clone(),
@@ -429,12 +753,42 @@ pub mod first {
 
         // Execute the swap
         msg!("Calling swap_to_sol with amount: {}", amount);
-        let result = swap::swap_to_sol(swap_to_sol_ctx, amount, data, authority_seeds)?;
+        let swapped = swap::swap_to_sol(swap_to_sol_ctx, amount, min_amount_out, data)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .fund_details
+            .record_trade(ctx.accounts.sol_mint.key(), now)?;
+        ctx.accounts.trade_ledger.record(TradeRecord::new(
+            ctx.accounts.usdc_mint.key(),
+            ctx.accounts.sol_mint.key(),
+            swapped,
+            ctx.accounts.queued_trade.authorization,
+        )?)?;
 
         msg!("USDC to SOL trade completed successfully");
+        emit_cpi!(TradeExecuted {
+            fund: fund_key,
+            trader: ctx.accounts.user_account.key(),
+            input_mint: ctx.accounts.usdc_mint.key(),
+            output_mint: ctx.accounts.sol_mint.key(),
+            amount_in: swapped.amount_in,
+            amount_out: swapped.amount_out,
+            timestamp: now,
+        });
         Ok(())
     }
 
+    pub fn rebalance(
+        ctx: Context<Rebalance>,
+        fund_id: String,
+        amount_in: u64,
+        min_amount_out: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        process_rebalance(ctx, fund_id, amount_in, min_amount_out, data)
+    }
+
 
 }
 
@@ -448,7 +802,7 @@ pub struct CompleteFundInfo {
     pub tokens_minted: u64,
     pub authority: Pubkey,
     pub start_time: i64,
-    pub status: u8,
+    pub status: FundStatus,
 }
 
 #[derive(Accounts)]
@@ -457,6 +811,7 @@ pub struct GetAllFunds<'info> {
     pub user: AccountInfo<'info>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(hedge_fund:Pubkey)]
 pub struct Initialize<'info> {
@@ -468,6 +823,18 @@ pub struct Initialize<'info> {
         space = 8 + 32
     )]
     pub hedge_fund_owner: Account<'info, HedgeFundOwner>,
+    #[account(
+        seeds = [MULTISIG_SEED],
+        bump = multisig.bump,
+        constraint = multisig.is_signer(&payer.key()) @ FundError::Unauthorized
+    )]
+    pub multisig: Account<'info, Multisig>,
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, &pending_action.id.to_le_bytes()],
+        bump = pending_action.bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
@@ -479,27 +846,23 @@ pub struct HedgeFundOwner {
 }
 
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(fund_id: String, amount: u64)]
 pub struct Trade<'info> {
     #[account(
-        seeds = [b"fund_details", fund_id.as_bytes(), user_account.key().as_ref()],
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_details.authority.as_ref()],
         bump,
-        mut
+        constraint = fund_details.roles.is_trader(&user_account.key()) @ FundError::UnauthorizedTrader,
+        mut,
+        constraint = fund_details.effective_status(Clock::get()?.unix_timestamp).allows_trading() @ FundError::InvalidFundStatus
     )]
     pub fund_details: Account<'info, FundDetails>,
 
-    #[account(
-        seeds = [b"fund_manager", fund_details.authority.as_ref()],
-        bump,
-        constraint = hedge_fund_owner.hedge_fund == user_account.key() @ FundError::UnauthorizedTrader
-    )]
-    pub hedge_fund_owner: Account<'info, HedgeFundOwner>,
-
-    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
     pub program_authority: SystemAccount<'info>,
 
-    #[account(mut, seeds = [WSOL_SEED], bump)]
+    #[account(mut, seeds = [WSOL_SEED, fund_details.key().as_ref()], bump)]
     /// CHECK: This is safe because we're only using it as a temp storage
     pub program_wsol_account: UncheckedAccount<'info>,
 
@@ -508,7 +871,7 @@ pub struct Trade<'info> {
 
     #[account(
         mut,
-        seeds = [VAULT_SEED, fund_id.as_bytes()],
+        seeds = [VAULT_SEED, fund_details.key().as_ref()],
         bump
     )]
     /// CHECK: This is a simple SOL vault owned by the system program
@@ -517,37 +880,77 @@ pub struct Trade<'info> {
     #[account(address = spl_token::native_mint::id())]
     pub sol_mint: Account<'info, Mint>,
 
+    #[account(seeds = [PRICE_FEED_SEED, sol_mint.key().as_ref()], bump = sol_price_feed.bump)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+
+    /// CHECK: SOL/USD price account, parsed by the oracle
+    #[account(address = sol_price_feed.feed @ FundError::PriceFeedMismatch)]
+    pub sol_feed: UncheckedAccount<'info>,
+
     /// Destination token mint - the token we're swapping to
+    #[account(constraint = asset_allowlist.is_allowed(&destination_mint.key()) @ FundError::AssetNotAllowed)]
     pub destination_mint: Account<'info, Mint>,
 
+    #[account(seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()], bump = asset_allowlist.bump)]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+
+    #[account(seeds = [PRICE_FEED_SEED, destination_mint.key().as_ref()], bump = destination_price_feed.bump)]
+    pub destination_price_feed: Account<'info, PriceFeed>,
+
+    /// CHECK: Price account registered for the destination mint, parsed by the oracle
+    #[account(address = destination_price_feed.feed @ FundError::PriceFeedMismatch)]
+    pub destination_feed: UncheckedAccount<'info>,
+
     /// Fund token account to store swapped tokens (PDA)
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), destination_mint.key().as_ref()],
+        bump
+    )]
     /// CHECK: Will be initialized if needed
     pub fund_token_account: UncheckedAccount<'info>,
 
+    #[account(mut, seeds = [TRADE_LEDGER_SEED, fund_details.key().as_ref()], bump = trade_ledger.bump)]
+    pub trade_ledger: Account<'info, TradeLedger>,
+
+    /// Queued swap this trade carries out
+    #[account(
+        mut,
+        seeds = [QUEUED_TRADE_SEED, fund_details.key().as_ref(), &queued_trade.id.to_le_bytes()],
+        bump = queued_trade.bump
+    )]
+    pub queued_trade: Account<'info, QueuedTrade>,
+
     pub jupiter_program: Program<'info, Jupiter>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(fund_id: String)]
 pub struct UsdcToSolTrade<'info> {
-    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
-    pub program_authority: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_details.authority.as_ref()],
+        bump,
+        constraint = fund_details.roles.is_trader(&user_account.key()) @ FundError::UnauthorizedTrader,
+        constraint = fund_details.effective_status(Clock::get()?.unix_timestamp).allows_trading() @ FundError::InvalidFundStatus
+    )]
+    pub fund_details: Account<'info, FundDetails>,
 
-    #[account(mut)]
-    pub destination_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
+    pub program_authority: SystemAccount<'info>,
 
     /// CHECK: program_wsol_account
-    #[account(mut, seeds = [WSOL_SEED], bump)]
+    #[account(mut, seeds = [WSOL_SEED, fund_details.key().as_ref()], bump)]
     pub program_wsol_account: UncheckedAccount<'info>,
 
-    /// CHECK: Anyone can call this instruction
-    pub user_account: AccountInfo<'info>, // Changed from Signer to AccountInfo
-
-    /// CHECK:fund_vault
     #[account(mut)]
+    pub user_account: Signer<'info>,
+
+    /// CHECK: This is a simple SOL vault owned by the system program
+    #[account(mut, seeds = [VAULT_SEED, fund_details.key().as_ref()], bump)]
     pub fund_vault: UncheckedAccount<'info>,
 
     #[account(address = spl_token::native_mint::id())]
@@ -557,13 +960,31 @@ pub struct UsdcToSolTrade<'info> {
     #[account(
         mut,
         // Only verify this is a token account with the correct mint and authority
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), usdc_mint.key().as_ref()],
+        bump,
         token::mint = usdc_mint,
         // IMPORTANT: Add constraint to ensure authority is program_authority
         constraint = fund_token_account.owner == program_authority.key() @ ErrorCode::IncorrectOwner
     )]
     pub fund_token_account: Account<'info, TokenAccount>,
 
+    #[account(constraint = asset_allowlist.is_allowed(&usdc_mint.key()) @ FundError::AssetNotAllowed)]
     pub usdc_mint: Account<'info, Mint>,
+
+    #[account(seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()], bump = asset_allowlist.bump)]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+
+    #[account(mut, seeds = [TRADE_LEDGER_SEED, fund_details.key().as_ref()], bump = trade_ledger.bump)]
+    pub trade_ledger: Account<'info, TradeLedger>,
+
+    /// Queued swap this trade carries out
+    #[account(
+        mut,
+        seeds = [QUEUED_TRADE_SEED, fund_details.key().as_ref(), &queued_trade.id.to_le_bytes()],
+        bump = queued_trade.bump
+    )]
+    pub queued_trade: Account<'info, QueuedTrade>,
+
     pub jupiter_program: Program<'info, Jupiter>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
@@ -571,17 +992,40 @@ pub struct UsdcToSolTrade<'info> {
 }
 
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(fund_id: String)]
 pub struct CreateFund<'info> {
     #[account(
         init,
         payer = user,
-        space = 8 + 32 + 32 + 8 + 8 + 32 + 10 + 32 + 8 + 8 + 8 + 1 + 4 + (4 + 32 + 8) * 10,
+        space = FundDetails::LEN,
         seeds = [b"fund_details", fund_id.as_bytes(), user.key().as_ref()],
         bump
     )]
     pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        init,
+        payer = user,
+        space = AssetAllowlist::LEN,
+        seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()],
+        bump
+    )]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+
+    #[account(
+        init,
+        payer = user,
+        space = TradeLedger::LEN,
+        seeds = [TRADE_LEDGER_SEED, fund_details.key().as_ref()],
+        bump
+    )]
+    pub trade_ledger: Account<'info, TradeLedger>,
+
+    #[account(seeds = [PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
     #[account(mut)]
     pub token_mint: Account<'info, Mint>,
 
@@ -599,26 +1043,19 @@ pub struct CreateFund<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
-#[derive(Accounts)]
-#[instruction(fund_id: String,fund_creator: Pubkey)]
-pub struct GetUserDeposits<'info> {
-    #[account(
-        seeds = [b"fund_details", fund_id.as_bytes(), fund_creator.as_ref()],
-        bump,
-    )]
-    pub fund_details: Account<'info, FundDetails>,
-}
-
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(fund_id: String)]
 pub struct DrainAllFunds<'info> {
-    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
     pub program_authority: SystemAccount<'info>,
 
     /// CHECK: This is a simple SOL vault owned by the system program, validated by PDA seeds
     #[account(
         mut,
-        seeds = [VAULT_SEED, fund_id.as_bytes()],
+        seeds = [VAULT_SEED, fund_details.key().as_ref()],
         bump,
         owner = system_program::ID
     )]
@@ -626,27 +1063,46 @@ pub struct DrainAllFunds<'info> {
 
     #[account(
         mut,
-        seeds = [FUND_TOKEN_SEED, fund_id.as_bytes(), usdc_mint.key().as_ref()],
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), usdc_mint.key().as_ref()],
         bump
     )]
     pub fund_token_account: Account<'info, TokenAccount>,
 
     /// CHECK: This is safe because we're only using it as a temporary WSOL storage for swaps
-    #[account(mut, seeds = [WSOL_SEED], bump)]
+    #[account(mut, seeds = [WSOL_SEED, fund_details.key().as_ref()], bump)]
     pub program_wsol_account: UncheckedAccount<'info>,
 
     pub usdc_mint: Account<'info, Mint>,
 
-    /// CHECK: Hardcoded destination address verified in constraint
+    #[account(seeds = [PROTOCOL_CONFIG_SEED], bump = protocol_config.bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [MULTISIG_SEED],
+        bump = multisig.bump,
+        constraint = multisig.is_signer(&authority.key()) @ FundError::Unauthorized
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, &pending_action.id.to_le_bytes()],
+        bump = pending_action.bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+
+    pub authority: Signer<'info>,
+
+    /// CHECK: Protocol treasury, verified against the config
     #[account(
         mut,
-        constraint = destination.key() == Pubkey::from_str("5rYp2nNjSYxqVDnGAqyjRozXmPxStjpdzjvHng7eVZjP").unwrap()
+        constraint = destination.key() == protocol_config.treasury @ FundError::Unauthorized
     )]
     pub destination: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        constraint = destination_token_account.owner == Pubkey::from_str("5rYp2nNjSYxqVDnGAqyjRozXmPxStjpdzjvHng7eVZjP").unwrap()
+        constraint = destination_token_account.owner == protocol_config.treasury @ FundError::Unauthorized
     )]
     pub destination_token_account: Account<'info, TokenAccount>,
 
@@ -656,10 +1112,11 @@ pub struct DrainAllFunds<'info> {
 
 #[account]
 pub struct FundDetails {
-    pub authority: Pubkey,
+    pub authority: Pubkey, // Creator, part of the fund's PDA seeds; permissions are in `roles`
     pub fund_token_mint: Pubkey, // The fund token mint address
     pub tokens_minted: u64,      // Total tokens minted so far
     pub start_time: i64,
+    pub fundraise_deadline: i64, // Investors can claim refunds after this if invest_threshold was not met
     pub fund_id: [u8; 32],
     pub fund_name: [u8; 10],   // Moved from FundDetails
     pub description: [u8; 32], // Moved from FundDescription
@@ -667,14 +1124,147 @@ pub struct FundDetails {
     pub total_deposit: u64,
     pub current_fund_token: u64,
     pub current_depost: u64,
-    pub user_deposits: Vec<UserDeposit>,
-    pub status: u8, // 0 = Active, 1 = Trade, 2 = Expired
+    pub status: FundStatus,
+    pub trade_count: u64,
+    pub last_trade_time: i64,
+    pub final_distribution_lamports: u64, // SOL left for shareholders once expired, paid out pro rata
+    pub final_distribution_shares: u64,   // Shares still to be redeemed against it
+    pub fee_schedule: FeeSchedule,
+    pub high_water_mark: u64,      // Highest NAV per share a performance fee was charged at, scaled by NAV_PER_SHARE_SCALE
+    pub last_crystallization: i64, // When the performance fee was last settled
+    pub last_fee_accrual: i64,     // Management fee has been charged up to here
+    pub governance: GovernanceConfig,
+    pub proposal_count: u64, // Id of the next proposal
+    pub queued_trade_count: u64, // Id of the next queued trade
+    pub roles: FundRoles,
+    pub holdings: Vec<Pubkey>, // Mints held in FUND_TOKEN_SEED accounts, valued in NAV
+}
+
+impl FundDetails {
+    pub const LEN: usize = 8 // discriminator
+        + 32 + 32 + 8 + 8 + 8 // authority, fund_token_mint, tokens_minted, start_time, fundraise_deadline
+        + 32 + 10 + 32 // fund_id, fund_name, description
+        + 8 + 8 + 8 + 8 // invest_threshold, total_deposit, current_fund_token, current_depost
+        + 1 + 8 + 8 // status, trade_count, last_trade_time
+        + 8 + 8 // final_distribution_lamports, final_distribution_shares
+        + FeeSchedule::LEN + 8 + 8 + 8 // fee_schedule, high_water_mark, last_crystallization, last_fee_accrual
+        + GovernanceConfig::LEN + 8 + 8 // governance, proposal_count, queued_trade_count
+        + FundRoles::LEN
+        + 4 + 32 * MAX_FUND_HOLDINGS; // holdings
+
+    /// Records that the fund holds `mint`, so NAV has to value it from now on.
+    pub fn track_holding(&mut self, mint: Pubkey) -> Result<()> {
+        if self.holdings.contains(&mint) {
+            return Ok(());
+        }
+        if self.holdings.len() >= MAX_FUND_HOLDINGS {
+            return err!(FundError::TooManyHoldings);
+        }
+        self.holdings.push(mint);
+        Ok(())
+    }
+
+    /// Charges the management fee for the time since `last_fee_accrual`, returning how many
+    /// shares to mint to the manager. Only funds the clock still has trading accrue, so a fund
+    /// past its term accrues nothing even before it is cranked; for any other status the clock
+    /// is just moved on, so that time is never charged.
+    pub fn accrue_management_fee(&mut self, outstanding_shares: u64, now: i64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.last_fee_accrual);
+        if elapsed <= 0 {
+            return Ok(0);
+        }
+        if !self.effective_status(now).allows_trading() || outstanding_shares == 0 {
+            self.last_fee_accrual = now;
+            return Ok(0);
+        }
+        let shares = self
+            .fee_schedule
+            .management_fee_shares(outstanding_shares, elapsed as u64)?;
+        // Leave the clock alone while the fee still rounds to nothing, so it keeps accruing
+        if shares > 0 {
+            self.last_fee_accrual = now;
+            msg!("Management fee: {} shares for {} seconds", shares, elapsed);
+        }
+        Ok(shares)
+    }
+
+    /// Settles both the management and the performance fee on a fund worth `nav`, returning
+    /// the total shares to mint to the manager.
+    pub fn settle_fees(&mut self, nav: u64, outstanding_shares: u64, now: i64) -> Result<u64> {
+        let management_shares = self.accrue_management_fee(outstanding_shares, now)?;
+        let outstanding_shares = outstanding_shares
+            .checked_add(management_shares)
+            .ok_or(FundError::OverflowError)?;
+        let performance_shares = self.settle_performance_fee(nav, outstanding_shares, now)?;
+        management_shares
+            .checked_add(performance_shares)
+            .ok_or_else(|| error!(FundError::OverflowError))
+    }
+
+    /// Settles the performance fee at `nav` if the crystallization period has passed,
+    /// returning how many shares to mint to the manager.
+    pub fn settle_performance_fee(&mut self, nav: u64, outstanding_shares: u64, now: i64) -> Result<u64> {
+        let due = self
+            .last_crystallization
+            .checked_add(self.fee_schedule.crystallization_period)
+            .ok_or(FundError::OverflowError)?;
+        if now < due {
+            return Ok(0);
+        }
+        let (shares, high_water_mark) =
+            self.fee_schedule
+                .performance_fee_shares(self.high_water_mark, nav, outstanding_shares)?;
+        self.high_water_mark = high_water_mark;
+        self.last_crystallization = now;
+        if shares > 0 {
+            msg!("Performance fee: {} shares, high-water mark now {}", shares, high_water_mark);
+        }
+        Ok(shares)
+    }
+
+    /// Moves the fund to `next`, rejecting anything the lifecycle does not allow.
+    pub fn transition_to(&mut self, next: FundStatus) -> Result<()> {
+        if !self.status.can_transition_to(next) {
+            msg!("Fund cannot move from {:?} to {:?}", self.status, next);
+            return err!(FundError::InvalidStatusTransition);
+        }
+        msg!("Fund status {:?} -> {:?}", self.status, next);
+        self.status = next;
+        Ok(())
+    }
+
+    /// True once the fundraise deadline has passed without reaching `invest_threshold`.
+    pub fn fundraise_failed(&self, now: i64) -> bool {
+        self.status == FundStatus::Fundraising
+            && self.total_deposit < self.invest_threshold
+            && now > self.fundraise_deadline
+    }
+
+    /// The status the clock has moved the fund to at `now`, whether or not `crank_lifecycle`
+    /// has recorded it yet. Instructions gate on this so an uncranked fund cannot keep taking
+    /// deposits or trading past its soft close or term.
+    pub fn effective_status(&self, now: i64) -> FundStatus {
+        let age = now.saturating_sub(self.start_time);
+        match self.status {
+            FundStatus::Trading | FundStatus::SoftClosed if age > THREE_MONTHS_SECONDS => FundStatus::WindingDown,
+            FundStatus::Trading if age > THREE_MONTHS_SECONDS - SOFT_CLOSE_WINDOW_SECONDS => FundStatus::SoftClosed,
+            status => status,
+        }
+    }
+
+    /// Bookkeeping shared by every instruction that swaps fund assets.
+    pub fn record_trade(&mut self, destination_mint: Pubkey, now: i64) -> Result<()> {
+        if destination_mint != spl_token::native_mint::id() {
+            self.track_holding(destination_mint)?;
+        }
+        self.trade_count = self.trade_count.checked_add(1).ok_or(FundError::OverflowError)?;
+        self.last_trade_time = now;
+        Ok(())
+    }
 }
 
-// Fund status constants
-pub const FUND_STATUS_ACTIVE: u8 = 0;
-pub const FUND_STATUS_TRADE: u8 = 1;
-pub const FUND_STATUS_EXPIRED: u8 = 2;
+// Maximum number of distinct token holdings per fund
+pub const MAX_FUND_HOLDINGS: usize = 8;
 
 // Three months in seconds (90 days)
 pub const THREE_MONTHS_SECONDS: i64 = 60 * 60 * 24 * 90;
@@ -687,7 +1277,7 @@ pub enum FundError {
     TokenMintMismatch,
     #[msg("Invalid mint authority")]
     InvalidMintAuthority,
-    #[msg("Cannot withdraw before 3 days from fund start")]
+    #[msg("Cannot claim a refund before the fundraise deadline")]
     WithdrawalTooEarly,
     #[msg("Cannot withdraw as investment threshold is already met")]
     ThresholdAlreadyMet,
@@ -708,25 +1298,156 @@ pub enum FundError {
     #[msg("Incorrect owner")]
     IncorrectOwner,
     #[msg("UnauthorizedTrader ")]
-    UnauthorizedTrader
+    UnauthorizedTrader,
+    #[msg("Signer is not allowed to perform this action")]
+    Unauthorized,
+    #[msg("Fund NAV cannot be computed")]
+    NavUnavailable,
+    #[msg("Price feed does not match the registered feed for this mint")]
+    PriceFeedMismatch,
+    #[msg("Account is not a valid price account")]
+    InvalidPriceAccount,
+    #[msg("Price is not currently available")]
+    PriceUnavailable,
+    #[msg("Holding account does not match the fund's token account for this mint")]
+    HoldingAccountMismatch,
+    #[msg("Fund already holds the maximum number of tokens")]
+    TooManyHoldings,
+    #[msg("Deposit is too small to mint any shares")]
+    DepositTooSmall,
+    #[msg("Invalid oracle configuration")]
+    InvalidOracleConfig,
+    #[msg("Oracle price is too old")]
+    StalePrice,
+    #[msg("Oracle price confidence interval is too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Swap returned less than the minimum amount out")]
+    SlippageExceeded,
+    #[msg("Swap spent more than the requested input amount")]
+    SwapInputExceeded,
+    #[msg("Rebalance source and destination mints must differ")]
+    InvalidRebalancePair,
+    #[msg("Mint is not on the fund's allowlist")]
+    AssetNotAllowed,
+    #[msg("Allowlist has too many, duplicate or empty mints")]
+    InvalidAllowlist,
+    #[msg("No change is queued")]
+    NoPendingChange,
+    #[msg("Timelock has not elapsed yet")]
+    TimelockNotElapsed,
+    #[msg("New allowlist drops a mint the fund still holds")]
+    AllowlistDropsHolding,
+    #[msg("Fundraise period must be positive and at most three months")]
+    InvalidFundraisePeriod,
+    #[msg("Fundraise missed its deadline and is refunding investors")]
+    FundraiseClosed,
+    #[msg("No shares to refund")]
+    NothingToRefund,
+    #[msg("Fund status does not allow this transition")]
+    InvalidStatusTransition,
+    #[msg("Fund is already in the status its clock allows")]
+    NoStatusChange,
+    #[msg("Fund holds none of this token to liquidate")]
+    NothingToLiquidate,
+    #[msg("Fee schedule exceeds the protocol's fee limits")]
+    FeeTooHigh,
+    #[msg("Fee limits cannot exceed 100%")]
+    InvalidFeeLimits,
+    #[msg("Invalid fee schedule")]
+    InvalidFeeSchedule,
+    #[msg("No fees to claim")]
+    NothingToClaim,
+    #[msg("Quorum and approval threshold must be at most 100% and the voting period positive")]
+    InvalidGovernanceConfig,
+    #[msg("Proposal must swap a positive amount between two different mints")]
+    InvalidProposal,
+    #[msg("Voting on this proposal has closed")]
+    VotingClosed,
+    #[msg("Voter holds no shares")]
+    NoVotingPower,
+    #[msg("Proposal has not been approved")]
+    ProposalNotApproved,
+    #[msg("Proposal has already been executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal execution window has passed")]
+    ProposalExpired,
+    #[msg("Trade does not match the approved proposal")]
+    ProposalMismatch,
+    #[msg("Trade needs exactly one approved proposal or poll result")]
+    TradeNotAuthorized,
+    #[msg("No poll oracle is registered")]
+    PollOracleNotSet,
+    #[msg("Poll result is not signed by the poll oracle")]
+    InvalidPollSignature,
+    #[msg("Poll result has expired")]
+    PollResultExpired,
+    #[msg("Poll result has already been traded on")]
+    PollResultAlreadyUsed,
+    #[msg("Trade does not match the poll result")]
+    PollResultMismatch,
+    #[msg("Queued trade is no longer pending")]
+    TradeNotPending,
+    #[msg("Queued trade execution window has passed")]
+    QueuedTradeExpired,
+    #[msg("Trade does not match the queued trade")]
+    QueuedTradeMismatch,
+    #[msg("Queued trade can no longer be vetoed")]
+    VetoWindowClosed,
+    #[msg("Delegation is invalid or not to this voter")]
+    InvalidDelegation,
+    #[msg("Holder has already voted on this proposal")]
+    AlreadyVoted,
+    #[msg("Too many, duplicate or empty traders, or no fee recipient")]
+    InvalidFundRoles,
+    #[msg("Multisig needs 1 to 10 distinct signers and a threshold of at most their number")]
+    InvalidMultisig,
+    #[msg("Action expiry must be positive and at most seven days")]
+    InvalidActionExpiry,
+    #[msg("Action has expired or was already executed")]
+    ActionNotPending,
+    #[msg("Signer has already approved this action")]
+    AlreadyApproved,
+    #[msg("Action does not have enough approvals")]
+    ThresholdNotMet,
+    #[msg("Instruction does not match the approved action")]
+    ActionMismatch,
+    #[msg("Swap route may not write to this fund account")]
+    RouteAccountNotAllowed
 }
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(fund_id: String)]
 pub struct CreateFundTokenAccount<'info> {
-    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    #[account(
+        mut,
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_details.authority.as_ref()],
+        bump,
+        constraint = fund_details.roles.owner == payer.key() @ FundError::Unauthorized
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
     pub program_authority: SystemAccount<'info>,
 
     #[account(
         mut,
-        seeds = [FUND_TOKEN_SEED, fund_id.as_bytes(), usdc_mint.key().as_ref()],
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), usdc_mint.key().as_ref()],
         bump
     )]
     /// CHECK: This account will be initialized as a token account
     pub fund_token_account: UncheckedAccount<'info>,
 
+    // Every holding is priced in NAV, so only allowed mints with a registered feed can be added
+    #[account(constraint = asset_allowlist.is_allowed(&usdc_mint.key()) @ FundError::AssetNotAllowed)]
     pub usdc_mint: Account<'info, Mint>,
 
+    #[account(seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()], bump = asset_allowlist.bump)]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+
+    #[account(seeds = [PRICE_FEED_SEED, usdc_mint.key().as_ref()], bump = usdc_price_feed.bump)]
+    pub usdc_price_feed: Account<'info, PriceFeed>,
+
     #[account(mut)]
     pub payer: Signer<'info>,
 
@@ -734,3 +1455,89 @@ pub struct CreateFundTokenAccount<'info> {
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_fund() -> FundDetails {
+        let creator = Pubkey::new_unique();
+        let mut roles = FundRoles::new(creator);
+        let traders = (0..MAX_FUND_TRADERS).map(|_| Pubkey::new_unique()).collect();
+        roles.set(traders, creator).unwrap();
+        roles.pending_owner = Pubkey::new_unique();
+        FundDetails {
+            authority: creator,
+            fund_token_mint: Pubkey::new_unique(),
+            tokens_minted: u64::MAX,
+            start_time: 0,
+            fundraise_deadline: 0,
+            fund_id: [1; 32],
+            fund_name: [1; 10],
+            description: [1; 32],
+            invest_threshold: 0,
+            total_deposit: 0,
+            current_fund_token: 0,
+            current_depost: 0,
+            status: FundStatus::Trading,
+            trade_count: 0,
+            last_trade_time: 0,
+            final_distribution_lamports: 0,
+            final_distribution_shares: 0,
+            fee_schedule: FeeSchedule {
+                deposit_fee_bps: 0,
+                redemption_fee_bps: 0,
+                manager_share_bps: 0,
+                performance_fee_bps: 0,
+                hurdle_bps: 0,
+                crystallization_period: 0,
+                management_fee_bps: 0,
+            },
+            high_water_mark: 0,
+            last_crystallization: 0,
+            last_fee_accrual: 0,
+            governance: GovernanceConfig {
+                quorum_bps: 0,
+                approval_threshold_bps: 0,
+                voting_period: 0,
+                trade_delay: 0,
+                veto_threshold_bps: 0,
+            },
+            proposal_count: 0,
+            queued_trade_count: 0,
+            roles,
+            holdings: (0..MAX_FUND_HOLDINGS).map(|_| Pubkey::new_unique()).collect(),
+        }
+    }
+
+    #[test]
+    fn full_fund_details_fit_their_space() {
+        assert_eq!(full_fund().try_to_vec().unwrap().len() + 8, FundDetails::LEN);
+    }
+
+    #[test]
+    fn status_follows_the_clock_before_any_crank() {
+        let fund = full_fund();
+        let soft_close = THREE_MONTHS_SECONDS - SOFT_CLOSE_WINDOW_SECONDS;
+        assert_eq!(fund.effective_status(soft_close), FundStatus::Trading);
+        assert_eq!(fund.effective_status(soft_close + 1), FundStatus::SoftClosed);
+        assert!(!fund.effective_status(soft_close + 1).accepts_deposits());
+        assert_eq!(fund.effective_status(THREE_MONTHS_SECONDS + 1), FundStatus::WindingDown);
+        assert!(!fund.effective_status(THREE_MONTHS_SECONDS + 1).allows_trading());
+
+        let fundraising = FundDetails { status: FundStatus::Fundraising, ..full_fund() };
+        assert_eq!(fundraising.effective_status(THREE_MONTHS_SECONDS + 1), FundStatus::Fundraising);
+    }
+
+    #[test]
+    fn management_fee_stops_at_the_term_before_any_crank() {
+        let mut fund = full_fund();
+        fund.fee_schedule.management_fee_bps = 200;
+        fund.last_fee_accrual = THREE_MONTHS_SECONDS - 30 * 24 * 60 * 60;
+        assert!(fund.clone().accrue_management_fee(1_000_000_000, THREE_MONTHS_SECONDS).unwrap() > 0);
+
+        assert_eq!(fund.accrue_management_fee(1_000_000_000, THREE_MONTHS_SECONDS + 1).unwrap(), 0);
+        assert_eq!(fund.last_fee_accrual, THREE_MONTHS_SECONDS + 1);
+        assert_eq!(fund.status, FundStatus::Trading);
+    }
+}
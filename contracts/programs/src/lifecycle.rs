@@ -0,0 +1,191 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::events::StatusChanged;
+use crate::nav::{circulating_shares, holding_amounts};
+use crate::{FundDetails, FundError, VAULT_SEED};
+
+// Deposits close this long before the fund's three month term ends
+pub const SOFT_CLOSE_WINDOW_SECONDS: i64 = 60 * 60 * 24 * 7;
+
+/// Where a fund is in its life. The only allowed moves are listed in `can_transition_to`:
+///
+/// Fundraising -> Trading -> SoftClosed -> WindingDown -> Expired -> Closed
+///
+/// with the shortcuts Fundraising -> Closed (failed fundraise, fully refunded) and
+/// Trading -> WindingDown (term ended before anyone cranked the soft close).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FundStatus {
+    /// Taking deposits until `invest_threshold` is met.
+    #[default]
+    Fundraising,
+    /// Threshold met: deposits, redemptions and trades.
+    Trading,
+    /// No new deposits; trading and redemptions continue until the term ends.
+    SoftClosed,
+    /// Term ended: holdings are liquidated back to SOL, no discretionary trades.
+    WindingDown,
+    /// Everything is in SOL and investors redeem their final share.
+    Expired,
+    /// No shares left outstanding.
+    Closed,
+}
+
+impl FundStatus {
+    pub fn can_transition_to(self, next: FundStatus) -> bool {
+        use FundStatus::*;
+        matches!(
+            (self, next),
+            (Fundraising, Trading)
+                | (Fundraising, Closed)
+                | (Trading, SoftClosed)
+                | (Trading, WindingDown)
+                | (SoftClosed, WindingDown)
+                | (WindingDown, Expired)
+                | (Expired, Closed)
+        )
+    }
+
+    pub fn accepts_deposits(self) -> bool {
+        matches!(self, FundStatus::Fundraising | FundStatus::Trading)
+    }
+
+    pub fn allows_trading(self) -> bool {
+        matches!(self, FundStatus::Trading | FundStatus::SoftClosed)
+    }
+}
+
+/// The status a fund should be in at `now`, or `None` if it is already there.
+///
+/// `outstanding_shares` is only consulted for the moves into `Closed`, and
+/// `holdings_liquidated` for the move from `WindingDown` into `Expired`.
+pub fn next_status(
+    fund_details: &FundDetails,
+    now: i64,
+    outstanding_shares: u64,
+    holdings_liquidated: bool,
+) -> Option<FundStatus> {
+    let next = match fund_details.status {
+        FundStatus::Fundraising if fund_details.total_deposit >= fund_details.invest_threshold => {
+            FundStatus::Trading
+        }
+        FundStatus::Fundraising if fund_details.fundraise_failed(now) && outstanding_shares == 0 => {
+            FundStatus::Closed
+        }
+        FundStatus::Trading | FundStatus::SoftClosed if fund_details.effective_status(now) != fund_details.status => {
+            fund_details.effective_status(now)
+        }
+        FundStatus::WindingDown if holdings_liquidated => FundStatus::Expired,
+        FundStatus::Expired if outstanding_shares == 0 => FundStatus::Closed,
+        _ => return None,
+    };
+    Some(next)
+}
+
+/// Permissionless: advances the fund as far as the clock and its state allow.
+///
+/// To take a winding-down fund to `Expired`, pass the fund's holding token accounts as
+/// remaining accounts, in `holdings` order. Once they are all empty the fund is entirely in
+/// SOL and the vault is snapshotted for the final pro-rata distribution.
+pub fn process_crank_lifecycle(ctx: Context<CrankLifecycle>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let outstanding_shares = circulating_shares(
+        ctx.accounts.fund_token_mint.supply,
+        ctx.accounts.fund_token_account.amount,
+    );
+
+    let holdings_liquidated = if ctx.remaining_accounts.is_empty() {
+        ctx.accounts.fund_details.holdings.is_empty()
+    } else {
+        holding_amounts(
+            ctx.program_id,
+            &ctx.accounts.fund_details,
+            &ctx.accounts.fund_details.key(),
+            ctx.remaining_accounts,
+        )?
+        .iter()
+        .all(|amount| *amount == 0)
+    };
+
+    let vault_reserve = Rent::get()?.minimum_balance(0);
+    let vault_liquidity = ctx.accounts.fund_vault.lamports().saturating_sub(vault_reserve);
+
+    let fund_key = ctx.accounts.fund_details.key();
+    let fund_details = &mut ctx.accounts.fund_details;
+    let start = fund_details.status;
+    let mut transitions = Vec::new();
+    while let Some(next) = next_status(fund_details, now, outstanding_shares, holdings_liquidated) {
+        transitions.push((fund_details.status, next));
+        fund_details.transition_to(next)?;
+        if next == FundStatus::Expired {
+            fund_details.final_distribution_lamports = vault_liquidity;
+            fund_details.final_distribution_shares = outstanding_shares;
+            msg!(
+                "Final distribution opened: {} lamports for {} shares",
+                vault_liquidity,
+                outstanding_shares
+            );
+        }
+    }
+
+    if fund_details.status == start {
+        return err!(FundError::NoStatusChange);
+    }
+
+    for (from, to) in transitions {
+        emit_cpi!(StatusChanged {
+            fund: fund_key,
+            from,
+            to,
+            timestamp: now,
+        });
+    }
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CrankLifecycle<'info> {
+    #[account(mut, has_one = fund_token_mint)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = fund_details
+    )]
+    pub fund_token_account: Account<'info, TokenAccount>,
+
+    #[account(seeds = [VAULT_SEED, fund_details.key().as_ref()], bump)]
+    /// CHECK: This is a simple SOL vault owned by the system program
+    pub fund_vault: UncheckedAccount<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FundStatus::*;
+
+    #[test]
+    fn lifecycle_only_moves_forward() {
+        assert!(Fundraising.can_transition_to(Trading));
+        assert!(Trading.can_transition_to(SoftClosed));
+        assert!(SoftClosed.can_transition_to(WindingDown));
+        assert!(WindingDown.can_transition_to(Expired));
+        assert!(Expired.can_transition_to(Closed));
+
+        assert!(!Trading.can_transition_to(Fundraising));
+        assert!(!SoftClosed.can_transition_to(Trading));
+        assert!(!WindingDown.can_transition_to(Trading));
+        assert!(!Closed.can_transition_to(Fundraising));
+        assert!(!Fundraising.can_transition_to(Fundraising));
+    }
+
+    #[test]
+    fn winding_down_cannot_be_skipped() {
+        assert!(!Trading.can_transition_to(Expired));
+        assert!(!SoftClosed.can_transition_to(Closed));
+        assert!(!Fundraising.can_transition_to(WindingDown));
+    }
+}
@@ -0,0 +1,185 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token::{Mint, Token, TokenAccount};
+use solana_program::{program::invoke_signed, system_instruction};
+
+use crate::events::TradeExecuted;
+use crate::ledger::{TradeLedger, TradeRecord, TRADE_LEDGER_SEED};
+use crate::lifecycle::FundStatus;
+use crate::nav::pro_rata;
+use crate::oracle::{Oracle, PriceFeed, PRICE_FEED_SEED};
+use crate::swap::{self, Jupiter};
+use crate::{FundDetails, FundError, AUTHORITY_SEED, FUND_TOKEN_SEED, VAULT_SEED, WSOL_SEED};
+
+// Most a liquidation may lose against the oracle value of the holding
+pub const LIQUIDATION_MAX_SLIPPAGE_BPS: u64 = 300;
+
+/// Keeper instruction for winding-down funds: swaps the fund's whole balance of one holding
+/// back to SOL in the vault. The minimum output is derived from the oracle, not the keeper,
+/// so a keeper cannot dump the fund's assets at a bad price.
+///
+/// The keeper fronts the rent for the temporary WSOL account and is paid it back from the
+/// vault once the swap has settled. The keeper also picks the route the fund's authority
+/// signs; `swap_to_sol` only lets it write to the holding and the WSOL account, and the
+/// holding can lose at most its liquidated balance.
+pub fn process_liquidate_holding(ctx: Context<LiquidateHolding>, data: Vec<u8>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let holding_mint = ctx.accounts.holding_mint.key();
+    let amount_in = ctx.accounts.holding_token_account.amount;
+    if amount_in == 0 {
+        return err!(FundError::NothingToLiquidate);
+    }
+
+    let oracle = Oracle::load(
+        ctx.program_id,
+        &[
+            ctx.accounts.holding_price_feed.to_account_info(),
+            ctx.accounts.holding_feed.to_account_info(),
+            ctx.accounts.sol_price_feed.to_account_info(),
+            ctx.accounts.sol_feed.to_account_info(),
+        ],
+        now,
+    )?;
+    let expected_out = oracle.value_in_lamports(&holding_mint, amount_in)?;
+    let min_amount_out = pro_rata(expected_out, 10_000 - LIQUIDATION_MAX_SLIPPAGE_BPS, 10_000)
+        .ok_or(FundError::OverflowError)?;
+    msg!(
+        "Liquidating {} of {}: oracle value {} lamports, minimum {}",
+        amount_in,
+        holding_mint,
+        expected_out,
+        min_amount_out
+    );
+
+    // Front the WSOL account rent, plus the authority's own reserve if it has none yet
+    let rent = Rent::get()?;
+    let wsol_rent = rent.minimum_balance(TokenAccount::LEN);
+    let authority_reserve = rent
+        .minimum_balance(0)
+        .saturating_sub(ctx.accounts.program_authority.lamports());
+    transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.keeper.to_account_info(),
+                to: ctx.accounts.program_authority.to_account_info(),
+            },
+        ),
+        wsol_rent + authority_reserve,
+    )?;
+
+    let swap_to_sol_ctx = Context {
+        program_id: ctx.program_id,
+        accounts: &mut swap::SwapToSOL {
+            fund_details: ctx.accounts.fund_details.clone(),
+            program_authority: ctx.accounts.program_authority.clone(),
+            program_wsol_account: ctx.accounts.program_wsol_account.clone(),
+            user_account: ctx.accounts.keeper.to_account_info(),
+            sol_mint: ctx.accounts.sol_mint.clone(),
+            user_token_account: ctx.accounts.holding_token_account.clone(),
+            fund_vault: ctx.accounts.fund_vault.clone(),
+            jupiter_program: ctx.accounts.jupiter_program.clone(),
+            token_program: ctx.accounts.token_program.clone(),
+            system_program: ctx.accounts.system_program.clone(),
+        },
+        remaining_accounts: ctx.remaining_accounts,
+        bumps: ctx.bumps.clone(),
+    };
+    let swapped = swap::swap_to_sol(swap_to_sol_ctx, amount_in, min_amount_out, data)?;
+
+    // The WSOL rent came back to the authority on close and was swept into the vault
+    let fund_key = ctx.accounts.fund_details.key();
+    let vault_bump = *ctx.bumps.get("fund_vault").unwrap();
+    let vault_seeds: &[&[u8]] = &[VAULT_SEED, fund_key.as_ref(), &[vault_bump]];
+    invoke_signed(
+        &system_instruction::transfer(&ctx.accounts.fund_vault.key(), &ctx.accounts.keeper.key(), wsol_rent),
+        &[
+            ctx.accounts.fund_vault.to_account_info(),
+            ctx.accounts.keeper.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+        &[vault_seeds],
+    )?;
+
+    ctx.accounts
+        .fund_details
+        .record_trade(ctx.accounts.sol_mint.key(), now)?;
+    ctx.accounts.trade_ledger.record(TradeRecord::new(
+        holding_mint,
+        ctx.accounts.sol_mint.key(),
+        swapped,
+        Pubkey::default(),
+    )?)?;
+
+    emit_cpi!(TradeExecuted {
+        fund: fund_key,
+        trader: ctx.accounts.keeper.key(),
+        input_mint: holding_mint,
+        output_mint: ctx.accounts.sol_mint.key(),
+        amount_in: swapped.amount_in,
+        amount_out: swapped.amount_out,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct LiquidateHolding<'info> {
+    #[account(
+        mut,
+        constraint = fund_details.status == FundStatus::WindingDown @ FundError::InvalidFundStatus,
+        constraint = fund_details.holdings.contains(&holding_mint.key()) @ FundError::HoldingAccountMismatch
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
+    pub program_authority: SystemAccount<'info>,
+
+    #[account(mut, seeds = [WSOL_SEED, fund_details.key().as_ref()], bump)]
+    /// CHECK: Per-fund scratch account that holds WSOL for the duration of the swap
+    pub program_wsol_account: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [VAULT_SEED, fund_details.key().as_ref()], bump)]
+    /// CHECK: This is a simple SOL vault owned by the system program
+    pub fund_vault: UncheckedAccount<'info>,
+
+    #[account(constraint = holding_mint.key() != spl_token::native_mint::id() @ FundError::NothingToLiquidate)]
+    pub holding_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), holding_mint.key().as_ref()],
+        bump,
+        token::mint = holding_mint,
+        token::authority = program_authority
+    )]
+    pub holding_token_account: Account<'info, TokenAccount>,
+
+    #[account(seeds = [PRICE_FEED_SEED, holding_mint.key().as_ref()], bump = holding_price_feed.bump)]
+    pub holding_price_feed: Account<'info, PriceFeed>,
+
+    /// CHECK: Price account registered for the holding mint, parsed by the oracle
+    #[account(address = holding_price_feed.feed @ FundError::PriceFeedMismatch)]
+    pub holding_feed: UncheckedAccount<'info>,
+
+    #[account(seeds = [PRICE_FEED_SEED, sol_mint.key().as_ref()], bump = sol_price_feed.bump)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+
+    /// CHECK: SOL/USD price account, parsed by the oracle
+    #[account(address = sol_price_feed.feed @ FundError::PriceFeedMismatch)]
+    pub sol_feed: UncheckedAccount<'info>,
+
+    #[account(address = spl_token::native_mint::id())]
+    pub sol_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(mut, seeds = [TRADE_LEDGER_SEED, fund_details.key().as_ref()], bump = trade_ledger.bump)]
+    pub trade_ledger: Account<'info, TradeLedger>,
+
+    pub jupiter_program: Program<'info, Jupiter>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,387 @@
+use anchor_lang::prelude::*;
+
+use crate::config::{ProtocolConfig, PROTOCOL_CONFIG_SEED};
+use crate::events::{ActionApproved, ActionExecuted, ActionProposed, MultisigUpdated};
+use crate::fees::FeeLimits;
+use crate::FundError;
+
+pub const MULTISIG_SEED: &[u8] = b"multisig";
+pub const PENDING_ACTION_SEED: &[u8] = b"pending_action";
+pub const MAX_MULTISIG_SIGNERS: usize = 10;
+pub const MAX_ACTION_LIFETIME_SECONDS: i64 = 60 * 60 * 24 * 7;
+
+/// A privileged operation, with the exact arguments the signers approve. The instruction
+/// that carries it out has to be called with the same arguments.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub enum PrivilegedAction {
+    DrainFund { fund: Pubkey },
+    RegisterFundManager { hedge_fund: Pubkey },
+    SetProtocolRoles { treasury: Pubkey, fee_recipient: Pubkey },
+    SetFeeLimits { fee_limits: FeeLimits },
+    SetPollOracle { poll_oracle: Pubkey },
+    ProposeProtocolAdmin { new_admin: Pubkey },
+    SetMultisig { signers: Vec<Pubkey>, threshold: u8 },
+    RegisterPriceFeed { mint: Pubkey, feed: Pubkey, max_staleness: i64, max_confidence_bps: u16 },
+}
+
+impl PrivilegedAction {
+    // Largest variant: SetMultisig with a full signer set
+    pub const LEN: usize = 1 + 4 + 32 * MAX_MULTISIG_SIGNERS + 1;
+}
+
+/// The protocol's M-of-N signer set, a singleton at `[MULTISIG_SEED]`.
+#[account]
+pub struct Multisig {
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub action_count: u64, // Id of the next pending action
+    pub bump: u8,
+}
+
+impl Multisig {
+    pub const LEN: usize = 8 + 4 + 32 * MAX_MULTISIG_SIGNERS + 1 + 8 + 1;
+
+    pub fn is_signer(&self, key: &Pubkey) -> bool {
+        self.signers.contains(key)
+    }
+
+    pub fn set(&mut self, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        if signers.is_empty()
+            || signers.len() > MAX_MULTISIG_SIGNERS
+            || threshold == 0
+            || threshold as usize > signers.len()
+        {
+            return err!(FundError::InvalidMultisig);
+        }
+        for (i, signer) in signers.iter().enumerate() {
+            if *signer == Pubkey::default() || signers[..i].contains(signer) {
+                return err!(FundError::InvalidMultisig);
+            }
+        }
+        self.signers = signers;
+        self.threshold = threshold;
+        Ok(())
+    }
+}
+
+/// A privileged action waiting for approvals, at `[PENDING_ACTION_SEED, id]`.
+#[account]
+pub struct PendingAction {
+    pub id: u64,
+    pub action: PrivilegedAction,
+    pub proposer: Pubkey,
+    pub approvals: Vec<Pubkey>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl PendingAction {
+    pub const LEN: usize = 8 + 8 + PrivilegedAction::LEN + 32 + 4 + 32 * MAX_MULTISIG_SIGNERS + 8 + 8 + 1 + 1;
+
+    /// Approvals from keys that are still in the signer set; removing a signer withdraws
+    /// their approvals on actions not yet executed.
+    pub fn approval_count(&self, multisig: &Multisig) -> usize {
+        self.approvals.iter().filter(|key| multisig.is_signer(key)).count()
+    }
+
+    pub fn approve(&mut self, multisig: &Multisig, approver: Pubkey, now: i64) -> Result<()> {
+        if !multisig.is_signer(&approver) {
+            return err!(FundError::Unauthorized);
+        }
+        if self.executed || now >= self.expires_at {
+            return err!(FundError::ActionNotPending);
+        }
+        if self.approvals.contains(&approver) {
+            return err!(FundError::AlreadyApproved);
+        }
+        if self.approvals.len() >= MAX_MULTISIG_SIGNERS {
+            // Only approvals of removed signers can fill the list up, drop those first
+            self.approvals.retain(|key| multisig.is_signer(key));
+        }
+        self.approvals.push(approver);
+        Ok(())
+    }
+
+    /// Checks the action is `expected`, approved by the threshold and unexpired, and marks
+    /// it executed.
+    pub fn consume(&mut self, multisig: &Multisig, expected: &PrivilegedAction, now: i64) -> Result<()> {
+        if self.executed || now >= self.expires_at {
+            return err!(FundError::ActionNotPending);
+        }
+        if self.action != *expected {
+            msg!("Approved action is {:?}", self.action);
+            return err!(FundError::ActionMismatch);
+        }
+        let approvals = self.approval_count(multisig);
+        if approvals < multisig.threshold as usize {
+            msg!("{} of {} approvals", approvals, multisig.threshold);
+            return err!(FundError::ThresholdNotMet);
+        }
+        self.executed = true;
+        Ok(())
+    }
+}
+
+/// Checks and uses up the approval for `expected`. Called by every privileged instruction.
+pub fn execute_approved(
+    multisig: &Multisig,
+    pending_action: &mut Account<PendingAction>,
+    expected: PrivilegedAction,
+) -> Result<ActionExecuted> {
+    let now = Clock::get()?.unix_timestamp;
+    pending_action.consume(multisig, &expected, now)?;
+    msg!("Executing approved action {}", pending_action.id);
+    Ok(ActionExecuted {
+        pending_action: pending_action.key(),
+        id: pending_action.id,
+        action: expected,
+        timestamp: now,
+    })
+}
+
+/// Bootstraps the signer set. Only the protocol admin can, and only once; afterwards the set
+/// changes through an approved `SetMultisig` action.
+pub fn process_initialize_multisig(ctx: Context<InitializeMultisig>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    multisig.set(signers, threshold)?;
+    multisig.action_count = 0;
+    multisig.bump = *ctx.bumps.get("multisig").unwrap();
+
+    msg!("Multisig initialized: {} of {}", multisig.threshold, multisig.signers.len());
+    emit_cpi!(MultisigUpdated {
+        signers: multisig.signers.clone(),
+        threshold: multisig.threshold,
+    });
+    Ok(())
+}
+
+/// Opens an action for approval; the proposer's approval is counted straight away.
+pub fn process_propose_action(ctx: Context<ProposeAction>, action: PrivilegedAction, expires_in: i64) -> Result<()> {
+    if expires_in <= 0 || expires_in > MAX_ACTION_LIFETIME_SECONDS {
+        return err!(FundError::InvalidActionExpiry);
+    }
+    let now = Clock::get()?.unix_timestamp;
+    let proposer = ctx.accounts.proposer.key();
+
+    let pending = &mut ctx.accounts.pending_action;
+    pending.id = ctx.accounts.multisig.action_count;
+    pending.action = action.clone();
+    pending.proposer = proposer;
+    pending.approvals = vec![proposer];
+    pending.created_at = now;
+    pending.expires_at = now.checked_add(expires_in).ok_or(FundError::OverflowError)?;
+    pending.executed = false;
+    pending.bump = *ctx.bumps.get("pending_action").unwrap();
+
+    let multisig = &mut ctx.accounts.multisig;
+    multisig.action_count = multisig.action_count.checked_add(1).ok_or(FundError::OverflowError)?;
+
+    msg!("Action {} proposed, expires at {}", pending.id, pending.expires_at);
+    emit_cpi!(ActionProposed {
+        pending_action: ctx.accounts.pending_action.key(),
+        id: ctx.accounts.pending_action.id,
+        action,
+        proposer,
+        expires_at: ctx.accounts.pending_action.expires_at,
+    });
+    Ok(())
+}
+
+pub fn process_approve_action(ctx: Context<ApproveAction>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let approver = ctx.accounts.approver.key();
+    ctx.accounts.pending_action.approve(&ctx.accounts.multisig, approver, now)?;
+
+    let approvals = ctx.accounts.pending_action.approval_count(&ctx.accounts.multisig);
+    msg!("Action {}: {} of {} approvals", ctx.accounts.pending_action.id, approvals, ctx.accounts.multisig.threshold);
+    emit_cpi!(ActionApproved {
+        pending_action: ctx.accounts.pending_action.key(),
+        approver,
+        approvals: approvals as u8,
+        threshold: ctx.accounts.multisig.threshold,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+pub fn process_update_multisig(ctx: Context<UpdateMultisig>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+    let executed = execute_approved(
+        &ctx.accounts.multisig,
+        &mut ctx.accounts.pending_action,
+        PrivilegedAction::SetMultisig { signers: signers.clone(), threshold },
+    )?;
+    emit_cpi!(executed);
+
+    let multisig = &mut ctx.accounts.multisig;
+    multisig.set(signers, threshold)?;
+    msg!("Multisig is now {} of {}", multisig.threshold, multisig.signers.len());
+    emit_cpi!(MultisigUpdated {
+        signers: multisig.signers.clone(),
+        threshold: multisig.threshold,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeMultisig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = Multisig::LEN,
+        seeds = [MULTISIG_SEED],
+        bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(seeds = [PROTOCOL_CONFIG_SEED], bump = protocol_config.bump, has_one = admin @ FundError::Unauthorized)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ProposeAction<'info> {
+    #[account(
+        mut,
+        seeds = [MULTISIG_SEED],
+        bump = multisig.bump,
+        constraint = multisig.is_signer(&proposer.key()) @ FundError::Unauthorized
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = PendingAction::LEN,
+        seeds = [PENDING_ACTION_SEED, &multisig.action_count.to_le_bytes()],
+        bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ApproveAction<'info> {
+    #[account(seeds = [MULTISIG_SEED], bump = multisig.bump)]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, &pending_action.id.to_le_bytes()],
+        bump = pending_action.bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+
+    pub approver: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateMultisig<'info> {
+    #[account(
+        mut,
+        seeds = [MULTISIG_SEED],
+        bump = multisig.bump,
+        constraint = multisig.is_signer(&executor.key()) @ FundError::Unauthorized
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, &pending_action.id.to_le_bytes()],
+        bump = pending_action.bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+
+    pub executor: Signer<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multisig(n: usize, threshold: u8) -> Multisig {
+        let mut multisig = Multisig { signers: vec![], threshold: 0, action_count: 0, bump: 0 };
+        multisig.set((0..n).map(|_| Pubkey::new_unique()).collect(), threshold).unwrap();
+        multisig
+    }
+
+    fn pending(action: PrivilegedAction, proposer: Pubkey) -> PendingAction {
+        PendingAction {
+            id: 0,
+            action,
+            proposer,
+            approvals: vec![proposer],
+            created_at: 0,
+            expires_at: 100,
+            executed: false,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn actions_need_threshold_of_distinct_current_signers() {
+        let mut signers = multisig(3, 2);
+        let action = PrivilegedAction::DrainFund { fund: Pubkey::new_unique() };
+        let mut pending = pending(action.clone(), signers.signers[0]);
+
+        assert!(pending.consume(&signers, &action, 10).is_err());
+        assert!(pending.approve(&signers, signers.signers[0], 10).is_err());
+        assert!(pending.approve(&signers, Pubkey::new_unique(), 10).is_err());
+        pending.approve(&signers, signers.signers[1], 10).unwrap();
+
+        // Removing a signer withdraws their approval
+        let remaining = vec![signers.signers[0], signers.signers[2]];
+        signers.set(remaining, 2).unwrap();
+        assert!(pending.consume(&signers, &action, 10).is_err());
+        pending.approve(&signers, signers.signers[1], 10).unwrap();
+
+        let other = PrivilegedAction::DrainFund { fund: Pubkey::new_unique() };
+        assert!(pending.consume(&signers, &other, 10).is_err());
+        assert!(pending.consume(&signers, &action, 100).is_err());
+        assert!(pending.consume(&signers, &action, 10).is_ok());
+        assert!(pending.consume(&signers, &action, 10).is_err());
+    }
+
+    #[test]
+    fn multisig_rejects_bad_signer_sets() {
+        let mut signers = multisig(2, 1);
+        let key = Pubkey::new_unique();
+        assert!(signers.set(vec![], 1).is_err());
+        assert!(signers.set(vec![key], 0).is_err());
+        assert!(signers.set(vec![key], 2).is_err());
+        assert!(signers.set(vec![key, key], 1).is_err());
+        assert!(signers.set(vec![Pubkey::default()], 1).is_err());
+    }
+
+    #[test]
+    fn largest_accounts_fit_their_space() {
+        let full = multisig(MAX_MULTISIG_SIGNERS, 1);
+        assert_eq!(full.try_to_vec().unwrap().len() + 8, Multisig::LEN);
+
+        let action = PrivilegedAction::SetMultisig { signers: full.signers.clone(), threshold: 1 };
+        let mut pending = pending(action, full.signers[0]);
+        pending.approvals = full.signers.clone();
+        assert_eq!(pending.try_to_vec().unwrap().len() + 8, PendingAction::LEN);
+
+        let feed = PrivilegedAction::RegisterPriceFeed {
+            mint: Pubkey::new_unique(),
+            feed: Pubkey::new_unique(),
+            max_staleness: 60,
+            max_confidence_bps: 100,
+        };
+        assert!(feed.try_to_vec().unwrap().len() <= PrivilegedAction::LEN);
+    }
+}
@@ -0,0 +1,120 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, TokenAccount};
+
+use crate::oracle::Oracle;
+use crate::{FundDetails, FundError, FUND_TOKEN_SEED};
+
+/// Splits an instruction's `remaining_accounts` into the fund's holding accounts and a
+/// validated `Oracle`.
+///
+/// The first `fund_details.holdings.len()` accounts are the fund's `FUND_TOKEN_SEED` token
+/// accounts, one per holding and in the same order. They are followed by
+/// `[price_feed, feed_account]` pairs for the native mint (SOL/USD) and for every holding.
+pub fn load_nav_accounts<'a, 'info>(
+    program_id: &Pubkey,
+    fund_details: &FundDetails,
+    remaining_accounts: &'a [AccountInfo<'info>],
+    now: i64,
+) -> Result<(&'a [AccountInfo<'info>], Oracle)> {
+    let holdings_len = fund_details.holdings.len();
+    if remaining_accounts.len() < holdings_len {
+        msg!("Expected token accounts for {} holdings", holdings_len);
+        return err!(FundError::NavUnavailable);
+    }
+    let (holding_accounts, price_accounts) = remaining_accounts.split_at(holdings_len);
+    let oracle = Oracle::load(program_id, price_accounts, now)?;
+    Ok((holding_accounts, oracle))
+}
+
+/// Computes the fund's net asset value in lamports.
+///
+/// The vault's SOL (`vault_lamports`, minus its rent-exempt reserve) is counted as is, and
+/// every mint in `fund_details.holdings` is valued through `oracle`.
+pub fn compute_nav<'info>(
+    program_id: &Pubkey,
+    fund_details: &FundDetails,
+    fund_key: &Pubkey,
+    vault_lamports: u64,
+    holding_accounts: &[AccountInfo<'info>],
+    oracle: &Oracle,
+) -> Result<u64> {
+    let vault_reserve = Rent::get()?.minimum_balance(0);
+    let mut nav = vault_lamports.saturating_sub(vault_reserve);
+
+    let amounts = holding_amounts(program_id, fund_details, fund_key, holding_accounts)?;
+    for (mint, amount) in fund_details.holdings.iter().zip(amounts) {
+        // Every holding needs a usable price, even an empty one, so NAV never silently skips assets
+        oracle.price_of(mint)?;
+        let value = oracle.value_in_lamports(mint, amount)?;
+        msg!("Holding {}: {} units worth {} lamports", mint, amount, value);
+
+        nav = nav.checked_add(value).ok_or(FundError::OverflowError)?;
+    }
+
+    Ok(nav)
+}
+
+/// Token balances of the fund's holdings, in `fund_details.holdings` order.
+///
+/// `holding_accounts` must be the fund's `FUND_TOKEN_SEED` accounts in that same order; an
+/// account that was never created counts as an empty holding.
+pub fn holding_amounts<'info>(
+    program_id: &Pubkey,
+    fund_details: &FundDetails,
+    fund_key: &Pubkey,
+    holding_accounts: &[AccountInfo<'info>],
+) -> Result<Vec<u64>> {
+    if holding_accounts.len() != fund_details.holdings.len() {
+        return err!(FundError::NavUnavailable);
+    }
+
+    let mut amounts = Vec::with_capacity(holding_accounts.len());
+    for (mint, holding_info) in fund_details.holdings.iter().zip(holding_accounts) {
+        let (expected_holding, _) = Pubkey::find_program_address(
+            &[FUND_TOKEN_SEED, fund_key.as_ref(), mint.as_ref()],
+            program_id,
+        );
+        if holding_info.key() != expected_holding {
+            return err!(FundError::HoldingAccountMismatch);
+        }
+
+        let amount = if holding_info.data_is_empty() {
+            0
+        } else {
+            if holding_info.owner != &token::ID {
+                return err!(FundError::HoldingAccountMismatch);
+            }
+            TokenAccount::try_deserialize(&mut holding_info.try_borrow_data()?.as_ref())?.amount
+        };
+        amounts.push(amount);
+    }
+    Ok(amounts)
+}
+
+/// Shares held by investors, i.e. the mint supply minus the fund's own unsold inventory.
+pub fn circulating_shares(mint_supply: u64, fund_inventory: u64) -> u64 {
+    mint_supply.saturating_sub(fund_inventory)
+}
+
+/// Shares owed for `amount` lamports deposited into a fund worth `nav` with `outstanding` shares.
+pub fn shares_for_deposit(amount: u64, nav: u64, outstanding: u64) -> Result<u64> {
+    if outstanding == 0 {
+        // Initial deposit case - 1:1 ratio
+        return Ok(amount);
+    }
+    if nav == 0 {
+        return err!(FundError::NavUnavailable);
+    }
+    pro_rata(amount, outstanding, nav).ok_or_else(|| error!(FundError::OverflowError))
+}
+
+/// `amount * numerator / denominator`, computed in u128 so it cannot overflow midway.
+pub fn pro_rata(amount: u64, numerator: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    let value = (amount as u128)
+        .checked_mul(numerator as u128)?
+        .checked_div(denominator as u128)?;
+    u64::try_from(value).ok()
+}
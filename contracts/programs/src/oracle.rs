@@ -0,0 +1,460 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::events::PriceFeedRegistered;
+use crate::multisig::{execute_approved, Multisig, PendingAction, PrivilegedAction, MULTISIG_SEED, PENDING_ACTION_SEED};
+use crate::nav::pro_rata;
+use crate::FundError;
+
+pub const PRICE_FEED_SEED: &[u8] = b"price_feed";
+
+// Pyth oracle program, the only owner a price account is trusted from
+mod pyth {
+    use anchor_lang::declare_id;
+    declare_id!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+}
+
+// Upper bound an admin can configure for a feed's max staleness (1 day)
+pub const MAX_PRICE_STALENESS_SECONDS: i64 = 60 * 60 * 24;
+
+// Pyth v2 price account layout (only the fields we read)
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+const PYTH_EXPO_OFFSET: usize = 20;
+const PYTH_TIMESTAMP_OFFSET: usize = 96;
+const PYTH_AGG_PRICE_OFFSET: usize = 208;
+const PYTH_AGG_CONF_OFFSET: usize = 216;
+const PYTH_AGG_STATUS_OFFSET: usize = 224;
+const PYTH_PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+/// Binds a mint to the Pyth-style price account used to value it, along with the
+/// freshness and confidence bounds a price must meet before the program trusts it.
+#[account]
+pub struct PriceFeed {
+    pub mint: Pubkey,
+    pub feed: Pubkey,
+    pub decimals: u8,
+    pub max_staleness: i64,      // Seconds since publish after which the price is rejected
+    pub max_confidence_bps: u16, // Widest confidence interval accepted, in bps of the price
+    pub bump: u8,
+}
+
+impl PriceFeed {
+    pub const LEN: usize = 8 + 32 + 32 + 1 + 8 + 2 + 1;
+}
+
+/// A USD price as published by the feed: `price ± conf`, scaled by `10^expo`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+/// Validated prices for a set of mints, loaded once per instruction.
+pub struct Oracle {
+    entries: Vec<(PriceFeed, OraclePrice)>,
+}
+
+impl Oracle {
+    /// Loads `[price_feed, feed_account]` pairs, rejecting any price that is stale or too uncertain.
+    pub fn load<'info>(program_id: &Pubkey, accounts: &[AccountInfo<'info>], now: i64) -> Result<Self> {
+        let pairs = accounts.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return err!(FundError::PriceFeedMismatch);
+        }
+        let mut entries = Vec::with_capacity(accounts.len() / 2);
+        for pair in pairs {
+            let price_feed = load_price_feed(program_id, &pair[0], &pair[1])?;
+            let price = read_price(&pair[1].try_borrow_data()?)?;
+            check_price(&price_feed, &price, now)?;
+            entries.push((price_feed, price));
+        }
+        Ok(Oracle { entries })
+    }
+
+    /// USD price of `mint`, or `PriceUnavailable` if no validated price was loaded for it.
+    pub fn price_of(&self, mint: &Pubkey) -> Result<OraclePrice> {
+        self.entry(mint).map(|(_, price)| *price)
+    }
+
+    /// Value of `amount` base units of `mint`, expressed in lamports.
+    pub fn value_in_lamports(&self, mint: &Pubkey, amount: u64) -> Result<u64> {
+        if amount == 0 {
+            return Ok(0);
+        }
+        let (price_feed, price) = self.entry(mint)?;
+        let sol_price = self.price_of(&spl_token::native_mint::id())?;
+        value_in_lamports(amount, price_feed.decimals, *price, sol_price)
+            .ok_or_else(|| error!(FundError::NavUnavailable))
+    }
+
+    /// Fails with `SlippageExceeded` unless `amount_out` of `output_mint` is worth at least the
+    /// `lamports_in` spent on it, less `max_slippage_bps`.
+    pub fn check_fill_for_lamports(
+        &self,
+        lamports_in: u64,
+        output_mint: &Pubkey,
+        amount_out: u64,
+        max_slippage_bps: u64,
+    ) -> Result<()> {
+        let value_out = self.value_in_lamports(output_mint, amount_out)?;
+        let floor = pro_rata(lamports_in, 10_000 - max_slippage_bps, 10_000).ok_or(FundError::OverflowError)?;
+        if value_out < floor {
+            msg!("Swap returned {} lamports of value for {}, at least {} needed", value_out, lamports_in, floor);
+            return err!(FundError::SlippageExceeded);
+        }
+        Ok(())
+    }
+
+    fn entry(&self, mint: &Pubkey) -> Result<&(PriceFeed, OraclePrice)> {
+        self.entries
+            .iter()
+            .find(|(price_feed, _)| price_feed.mint == *mint)
+            .ok_or_else(|| {
+                msg!("No price loaded for mint {}", mint);
+                error!(FundError::PriceUnavailable)
+            })
+    }
+}
+
+/// Points `mint` at a Pyth price account. Runs only as an approved multisig action, since the
+/// feed decides every NAV the fund is deposited into and redeemed at.
+pub fn process_register_price_feed(
+    ctx: Context<RegisterPriceFeed>,
+    feed: Pubkey,
+    max_staleness: i64,
+    max_confidence_bps: u16,
+) -> Result<()> {
+    let executed = execute_approved(
+        &ctx.accounts.multisig,
+        &mut ctx.accounts.pending_action,
+        PrivilegedAction::RegisterPriceFeed {
+            mint: ctx.accounts.mint.key(),
+            feed,
+            max_staleness,
+            max_confidence_bps,
+        },
+    )?;
+    emit_cpi!(executed);
+
+    read_price(&ctx.accounts.feed_account.try_borrow_data()?)?;
+    if max_staleness <= 0 || max_staleness > MAX_PRICE_STALENESS_SECONDS {
+        return err!(FundError::InvalidOracleConfig);
+    }
+    if max_confidence_bps == 0 || max_confidence_bps > 10_000 {
+        return err!(FundError::InvalidOracleConfig);
+    }
+
+    let price_feed = &mut ctx.accounts.price_feed;
+    price_feed.mint = ctx.accounts.mint.key();
+    price_feed.feed = feed;
+    price_feed.decimals = ctx.accounts.mint.decimals;
+    price_feed.max_staleness = max_staleness;
+    price_feed.max_confidence_bps = max_confidence_bps;
+    price_feed.bump = *ctx.bumps.get("price_feed").unwrap();
+
+    msg!("Registered price feed {} for mint {}", feed, price_feed.mint);
+    emit_cpi!(PriceFeedRegistered {
+        mint: price_feed.mint,
+        feed,
+        max_staleness,
+        max_confidence_bps,
+    });
+    Ok(())
+}
+
+/// Loads the `PriceFeed` registry entry and checks `feed_account` is the feed it points to.
+fn load_price_feed<'info>(
+    program_id: &Pubkey,
+    price_feed_info: &AccountInfo<'info>,
+    feed_account: &AccountInfo<'info>,
+) -> Result<PriceFeed> {
+    if price_feed_info.owner != program_id {
+        return err!(FundError::PriceFeedMismatch);
+    }
+    let price_feed = PriceFeed::try_deserialize(&mut price_feed_info.try_borrow_data()?.as_ref())?;
+    let expected = Pubkey::create_program_address(
+        &[PRICE_FEED_SEED, price_feed.mint.as_ref(), &[price_feed.bump]],
+        program_id,
+    )
+    .map_err(|_| error!(FundError::PriceFeedMismatch))?;
+    if price_feed_info.key() != expected || price_feed.feed != feed_account.key() {
+        return err!(FundError::PriceFeedMismatch);
+    }
+    if feed_account.owner != &pyth::ID {
+        return err!(FundError::InvalidPriceAccount);
+    }
+    Ok(price_feed)
+}
+
+/// Parses the aggregate price out of a Pyth-style price account.
+pub fn read_price(data: &[u8]) -> Result<OraclePrice> {
+    if data.len() < PYTH_PRICE_ACCOUNT_MIN_LEN
+        || read_u32(data, 0) != PYTH_MAGIC
+        || read_u32(data, 8) != PYTH_PRICE_ACCOUNT_TYPE
+    {
+        return err!(FundError::InvalidPriceAccount);
+    }
+    if read_u32(data, PYTH_AGG_STATUS_OFFSET) != PYTH_STATUS_TRADING {
+        return err!(FundError::PriceUnavailable);
+    }
+    let price = read_i64(data, PYTH_AGG_PRICE_OFFSET);
+    if price <= 0 {
+        return err!(FundError::PriceUnavailable);
+    }
+    Ok(OraclePrice {
+        price,
+        conf: read_u64(data, PYTH_AGG_CONF_OFFSET),
+        expo: read_u32(data, PYTH_EXPO_OFFSET) as i32,
+        publish_time: read_i64(data, PYTH_TIMESTAMP_OFFSET),
+    })
+}
+
+/// Enforces the feed's staleness and confidence bounds at time `now`.
+pub fn check_price(price_feed: &PriceFeed, price: &OraclePrice, now: i64) -> Result<()> {
+    let age = now.saturating_sub(price.publish_time);
+    if age > price_feed.max_staleness {
+        msg!("Price for {} is {}s old, max is {}s", price_feed.mint, age, price_feed.max_staleness);
+        return err!(FundError::StalePrice);
+    }
+    // conf / price > max_confidence_bps / 10_000
+    let conf_scaled = (price.conf as u128) * 10_000;
+    let max_scaled = (price.price as u128) * price_feed.max_confidence_bps as u128;
+    if conf_scaled > max_scaled {
+        msg!("Price for {} has confidence {} on {}", price_feed.mint, price.conf, price.price);
+        return err!(FundError::PriceConfidenceTooWide);
+    }
+    Ok(())
+}
+
+/// Converts `amount` base units of a token into lamports using the token's and SOL's USD prices.
+pub fn value_in_lamports(
+    amount: u64,
+    decimals: u8,
+    price: OraclePrice,
+    sol_price: OraclePrice,
+) -> Option<u64> {
+    // amount * price * 10^(expo - decimals) / (sol_price * 10^(sol_expo - 9))
+    let scale = price.expo as i64 - sol_price.expo as i64 + 9 - decimals as i64;
+    let mut numerator = (amount as u128).checked_mul(price.price as u128)?;
+    let mut denominator = sol_price.price as u128;
+    if scale >= 0 {
+        numerator = numerator.checked_mul(10u128.checked_pow(scale as u32)?)?;
+    } else {
+        denominator = denominator.checked_mul(10u128.checked_pow((-scale) as u32)?)?;
+    }
+    u64::try_from(numerator / denominator).ok()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(feed: Pubkey)]
+pub struct RegisterPriceFeed<'info> {
+    #[account(
+        init_if_needed,
+        payer = executor,
+        space = PriceFeed::LEN,
+        seeds = [PRICE_FEED_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(address = feed @ FundError::PriceFeedMismatch, owner = pyth::ID @ FundError::InvalidPriceAccount)]
+    /// CHECK: Parsed as a Pyth price account before it is registered
+    pub feed_account: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [MULTISIG_SEED],
+        bump = multisig.bump,
+        constraint = multisig.is_signer(&executor.key()) @ FundError::Unauthorized
+    )]
+    pub multisig: Account<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [PENDING_ACTION_SEED, &pending_action.id.to_le_bytes()],
+        bump = pending_action.bump
+    )]
+    pub pending_action: Account<'info, PendingAction>,
+
+    #[account(mut)]
+    pub executor: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    /// Builds a Pyth v2 price account with the given aggregate values.
+    fn mock_price_account(price: i64, conf: u64, expo: i32, publish_time: i64, status: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 3312];
+        data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+        data[PYTH_EXPO_OFFSET..PYTH_EXPO_OFFSET + 4].copy_from_slice(&expo.to_le_bytes());
+        data[PYTH_TIMESTAMP_OFFSET..PYTH_TIMESTAMP_OFFSET + 8].copy_from_slice(&publish_time.to_le_bytes());
+        data[PYTH_AGG_PRICE_OFFSET..PYTH_AGG_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+        data[PYTH_AGG_CONF_OFFSET..PYTH_AGG_CONF_OFFSET + 8].copy_from_slice(&conf.to_le_bytes());
+        data[PYTH_AGG_STATUS_OFFSET..PYTH_AGG_STATUS_OFFSET + 4].copy_from_slice(&status.to_le_bytes());
+        data
+    }
+
+    fn mock_price_feed(mint: Pubkey, decimals: u8) -> PriceFeed {
+        PriceFeed {
+            mint,
+            feed: Pubkey::new_unique(),
+            decimals,
+            max_staleness: 60,
+            max_confidence_bps: 100,
+            bump: 255,
+        }
+    }
+
+    fn sol_usd(price: i64) -> OraclePrice {
+        OraclePrice { price, conf: 0, expo: -8, publish_time: NOW }
+    }
+
+    #[test]
+    fn reads_aggregate_price() {
+        let data = mock_price_account(150_00000000, 5_000000, -8, NOW, PYTH_STATUS_TRADING);
+        let price = read_price(&data).unwrap();
+        assert_eq!(
+            price,
+            OraclePrice { price: 150_00000000, conf: 5_000000, expo: -8, publish_time: NOW }
+        );
+    }
+
+    #[test]
+    fn rejects_non_price_accounts() {
+        let mut data = mock_price_account(1, 0, -8, NOW, PYTH_STATUS_TRADING);
+        data[0] = 0;
+        assert_eq!(read_price(&data).unwrap_err(), error!(FundError::InvalidPriceAccount));
+        assert_eq!(read_price(&[0u8; 64]).unwrap_err(), error!(FundError::InvalidPriceAccount));
+    }
+
+    #[test]
+    fn rejects_halted_or_non_positive_prices() {
+        let halted = mock_price_account(150_00000000, 0, -8, NOW, 0);
+        assert_eq!(read_price(&halted).unwrap_err(), error!(FundError::PriceUnavailable));
+        let negative = mock_price_account(-1, 0, -8, NOW, PYTH_STATUS_TRADING);
+        assert_eq!(read_price(&negative).unwrap_err(), error!(FundError::PriceUnavailable));
+    }
+
+    #[test]
+    fn only_reads_prices_from_pyth_accounts() {
+        let mint = Pubkey::new_unique();
+        let (price_feed_key, bump) = Pubkey::find_program_address(&[PRICE_FEED_SEED, mint.as_ref()], &crate::ID);
+        let price_feed = PriceFeed { bump, ..mock_price_feed(mint, 6) };
+        let mut price_feed_data = vec![];
+        price_feed.try_serialize(&mut price_feed_data).unwrap();
+        let (mut pyth_data, mut spoofed_data) = (
+            mock_price_account(1_00000000, 0, -8, NOW, PYTH_STATUS_TRADING),
+            mock_price_account(1_00000000, 0, -8, NOW, PYTH_STATUS_TRADING),
+        );
+        let (mut price_feed_lamports, mut pyth_lamports, mut spoofed_lamports) = (0, 0, 0);
+        let spoofer = Pubkey::new_unique();
+        let price_feed_info = AccountInfo::new(
+            &price_feed_key, false, false, &mut price_feed_lamports, &mut price_feed_data, &crate::ID, false, 0,
+        );
+        let pyth_info = AccountInfo::new(&price_feed.feed, false, false, &mut pyth_lamports, &mut pyth_data, &pyth::ID, false, 0);
+        let spoofed_info =
+            AccountInfo::new(&price_feed.feed, false, false, &mut spoofed_lamports, &mut spoofed_data, &spoofer, false, 0);
+
+        assert!(load_price_feed(&crate::ID, &price_feed_info, &pyth_info).is_ok());
+        assert_eq!(
+            load_price_feed(&crate::ID, &price_feed_info, &spoofed_info).err(),
+            Some(error!(FundError::InvalidPriceAccount))
+        );
+    }
+
+    #[test]
+    fn enforces_max_staleness() {
+        let feed = mock_price_feed(Pubkey::new_unique(), 6);
+        let fresh = read_price(&mock_price_account(1_00000000, 0, -8, NOW - 60, PYTH_STATUS_TRADING)).unwrap();
+        assert!(check_price(&feed, &fresh, NOW).is_ok());
+        let stale = read_price(&mock_price_account(1_00000000, 0, -8, NOW - 61, PYTH_STATUS_TRADING)).unwrap();
+        assert_eq!(check_price(&feed, &stale, NOW).unwrap_err(), error!(FundError::StalePrice));
+    }
+
+    #[test]
+    fn enforces_max_confidence() {
+        let feed = mock_price_feed(Pubkey::new_unique(), 6);
+        // 1% of the price is exactly the 100 bps limit
+        let tight = read_price(&mock_price_account(1_00000000, 1_000000, -8, NOW, PYTH_STATUS_TRADING)).unwrap();
+        assert!(check_price(&feed, &tight, NOW).is_ok());
+        let wide = read_price(&mock_price_account(1_00000000, 1_000001, -8, NOW, PYTH_STATUS_TRADING)).unwrap();
+        assert_eq!(check_price(&feed, &wide, NOW).unwrap_err(), error!(FundError::PriceConfidenceTooWide));
+    }
+
+    #[test]
+    fn values_tokens_in_lamports() {
+        // 100 USDC (6 decimals) at $1 with SOL at $100 is 1 SOL
+        let usdc = OraclePrice { price: 1_00000000, conf: 0, expo: -8, publish_time: NOW };
+        assert_eq!(value_in_lamports(100_000000, 6, usdc, sol_usd(100_00000000)), Some(1_000_000_000));
+
+        // 2 SOL priced through the SOL feed itself
+        let sol = sol_usd(100_00000000);
+        assert_eq!(value_in_lamports(2_000_000_000, 9, sol, sol), Some(2_000_000_000));
+
+        // A 5-decimal token at $0.00002 with a -10 exponent, SOL at $50
+        let bonk = OraclePrice { price: 200_000, conf: 0, expo: -10, publish_time: NOW };
+        assert_eq!(value_in_lamports(100_000_000_000, 5, bonk, sol_usd(50_00000000)), Some(400_000_000));
+    }
+
+    #[test]
+    fn fills_are_held_to_the_oracle_price() {
+        let usdc_mint = Pubkey::new_unique();
+        let usdc = OraclePrice { price: 1_00000000, conf: 0, expo: -8, publish_time: NOW };
+        let oracle = Oracle {
+            entries: vec![
+                (mock_price_feed(spl_token::native_mint::id(), 9), sol_usd(100_00000000)),
+                (mock_price_feed(usdc_mint, 6), usdc),
+            ],
+        };
+        // 1 SOL is worth 100 USDC, so 3% slippage allows down to 97 USDC
+        assert!(oracle.check_fill_for_lamports(1_000_000_000, &usdc_mint, 97_000000, 300).is_ok());
+        assert_eq!(
+            oracle.check_fill_for_lamports(1_000_000_000, &usdc_mint, 96_999999, 300).unwrap_err(),
+            error!(FundError::SlippageExceeded)
+        );
+    }
+
+    #[test]
+    fn price_of_requires_a_loaded_price() {
+        let usdc_mint = Pubkey::new_unique();
+        let usdc = OraclePrice { price: 1_00000000, conf: 0, expo: -8, publish_time: NOW };
+        let oracle = Oracle {
+            entries: vec![
+                (mock_price_feed(spl_token::native_mint::id(), 9), sol_usd(100_00000000)),
+                (mock_price_feed(usdc_mint, 6), usdc),
+            ],
+        };
+        assert_eq!(oracle.price_of(&usdc_mint).unwrap(), usdc);
+        assert_eq!(oracle.value_in_lamports(&usdc_mint, 50_000000).unwrap(), 500_000_000);
+        assert_eq!(
+            oracle.price_of(&Pubkey::new_unique()).unwrap_err(),
+            error!(FundError::PriceUnavailable)
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use anchor_lang::prelude::*;
+use solana_program::ed25519_program;
+use solana_program::instruction::Instruction;
+use solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+
+use crate::config::{ProtocolConfig, PROTOCOL_CONFIG_SEED};
+use crate::events::PollResultRecorded;
+use crate::{FundDetails, FundError};
+
+pub const POLL_RESULT_SEED: &[u8] = b"poll_result";
+// Prefixed to the signed bytes so a poll-oracle signature cannot be replayed as anything else
+pub const POLL_RESULT_DOMAIN: &[u8] = b"kabal-poll-result-v1";
+
+// Layout of the ed25519 program's instruction data, see `solana_sdk::ed25519_instruction`
+const ED25519_OFFSETS_START: usize = 2;
+const ED25519_OFFSETS_LEN: usize = 14;
+const ED25519_PUBKEY_LEN: usize = 32;
+const ED25519_SIGNATURE_LEN: usize = 64;
+// Offsets with this instruction index point into the ed25519 instruction itself
+const ED25519_CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+/// The outcome of a Telegram poll as signed by the poll oracle. The oracle signs
+/// `POLL_RESULT_DOMAIN` followed by the borsh encoding of this struct.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SignedPollResult {
+    pub fund: Pubkey,
+    pub poll_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub expires_at: i64, // The trade has to happen before this
+}
+
+impl SignedPollResult {
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut message = POLL_RESULT_DOMAIN.to_vec();
+        message.extend(self.try_to_vec()?);
+        Ok(message)
+    }
+}
+
+/// A verified poll result waiting to be traded on, at `[POLL_RESULT_SEED, fund, poll_id]`.
+#[account]
+pub struct PollResult {
+    pub fund: Pubkey,
+    pub poll_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub expires_at: i64,
+    pub consumed: bool,
+    pub bump: u8,
+}
+
+impl PollResult {
+    pub const LEN: usize = 8 + 32 + 8 + 32 + 32 + 8 + 8 + 8 + 1 + 1;
+
+    /// Checks the poll authorizes a swap of `amount` from `input_mint` into `output_mint` at
+    /// `now`, and marks it used.
+    pub fn consume(
+        &mut self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        min_amount_out: u64,
+        now: i64,
+    ) -> Result<()> {
+        if self.consumed {
+            return err!(FundError::PollResultAlreadyUsed);
+        }
+        if now >= self.expires_at {
+            return err!(FundError::PollResultExpired);
+        }
+        if *input_mint != self.input_mint
+            || *output_mint != self.output_mint
+            || amount != self.amount
+            || min_amount_out < self.min_amount_out
+        {
+            msg!(
+                "Poll approved {} {} -> {} (min {})",
+                self.amount,
+                self.input_mint,
+                self.output_mint,
+                self.min_amount_out
+            );
+            return err!(FundError::PollResultMismatch);
+        }
+        self.consumed = true;
+        Ok(())
+    }
+}
+
+/// Stores a poll result signed by the protocol's poll oracle. The signature is checked by
+/// the ed25519 program in the instruction right before this one; here we only confirm that
+/// instruction verified the oracle's key over exactly this result. Anyone may relay it.
+pub fn process_record_poll_result(ctx: Context<RecordPollResult>, result: SignedPollResult) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let fund_key = ctx.accounts.fund_details.key();
+    if result.fund != fund_key {
+        return err!(FundError::PollResultMismatch);
+    }
+    if now >= result.expires_at {
+        return err!(FundError::PollResultExpired);
+    }
+    if result.amount == 0 || result.input_mint == result.output_mint {
+        return err!(FundError::InvalidProposal);
+    }
+
+    let instructions = ctx.accounts.instructions_sysvar.to_account_info();
+    let current = load_current_index_checked(&instructions)?;
+    if current == 0 {
+        return err!(FundError::InvalidPollSignature);
+    }
+    let signature_ix = load_instruction_at_checked(current as usize - 1, &instructions)?;
+    verify_ed25519_instruction(&signature_ix, &ctx.accounts.protocol_config.poll_oracle, &result.message()?)?;
+
+    let poll_result = &mut ctx.accounts.poll_result;
+    poll_result.fund = fund_key;
+    poll_result.poll_id = result.poll_id;
+    poll_result.input_mint = result.input_mint;
+    poll_result.output_mint = result.output_mint;
+    poll_result.amount = result.amount;
+    poll_result.min_amount_out = result.min_amount_out;
+    poll_result.expires_at = result.expires_at;
+    poll_result.consumed = false;
+    poll_result.bump = *ctx.bumps.get("poll_result").unwrap();
+
+    msg!("Recorded poll {} result, valid until {}", result.poll_id, result.expires_at);
+    emit_cpi!(PollResultRecorded {
+        fund: fund_key,
+        poll_result: ctx.accounts.poll_result.key(),
+        poll_id: result.poll_id,
+        input_mint: result.input_mint,
+        output_mint: result.output_mint,
+        amount: result.amount,
+        min_amount_out: result.min_amount_out,
+        expires_at: result.expires_at,
+    });
+    Ok(())
+}
+
+/// Checks `ix` is an ed25519 program instruction verifying exactly one signature by `signer`
+/// over `message`, with all of its data inside the instruction itself.
+pub fn verify_ed25519_instruction(ix: &Instruction, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    if ix.program_id != ed25519_program::id() || !ix.accounts.is_empty() {
+        return err!(FundError::InvalidPollSignature);
+    }
+    let data = &ix.data;
+    if data.len() < ED25519_OFFSETS_START + ED25519_OFFSETS_LEN || data[0] != 1 {
+        return err!(FundError::InvalidPollSignature);
+    }
+
+    let offset = |i: usize| {
+        let at = ED25519_OFFSETS_START + 2 * i;
+        u16::from_le_bytes([data[at], data[at + 1]])
+    };
+    let (signature_offset, signature_ix) = (offset(0) as usize, offset(1));
+    let (pubkey_offset, pubkey_ix) = (offset(2) as usize, offset(3));
+    let (message_offset, message_len, message_ix) = (offset(4) as usize, offset(5) as usize, offset(6));
+    if signature_ix != ED25519_CURRENT_INSTRUCTION
+        || pubkey_ix != ED25519_CURRENT_INSTRUCTION
+        || message_ix != ED25519_CURRENT_INSTRUCTION
+        || signature_offset + ED25519_SIGNATURE_LEN > data.len()
+    {
+        return err!(FundError::InvalidPollSignature);
+    }
+
+    let signed_by = data.get(pubkey_offset..pubkey_offset + ED25519_PUBKEY_LEN);
+    let signed_message = data.get(message_offset..message_offset + message_len);
+    if signed_by != Some(signer.as_ref()) || signed_message != Some(message) {
+        return err!(FundError::InvalidPollSignature);
+    }
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(result: SignedPollResult)]
+pub struct RecordPollResult<'info> {
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = PollResult::LEN,
+        seeds = [POLL_RESULT_SEED, fund_details.key().as_ref(), &result.poll_id.to_le_bytes()],
+        bump
+    )]
+    pub poll_result: Account<'info, PollResult>,
+
+    #[account(
+        seeds = [PROTOCOL_CONFIG_SEED],
+        bump = protocol_config.bump,
+        constraint = protocol_config.poll_oracle != Pubkey::default() @ FundError::PollOracleNotSet
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: The instructions sysvar, read to find the ed25519 verification
+    #[account(address = solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ed25519_ix(signer: &Pubkey, message: &[u8]) -> Instruction {
+        // One signature, followed by the public key, signature and message
+        let pubkey_offset = ED25519_OFFSETS_START + ED25519_OFFSETS_LEN;
+        let signature_offset = pubkey_offset + ED25519_PUBKEY_LEN;
+        let message_offset = signature_offset + ED25519_SIGNATURE_LEN;
+        let mut data = vec![1u8, 0];
+        for value in [
+            signature_offset as u16,
+            u16::MAX,
+            pubkey_offset as u16,
+            u16::MAX,
+            message_offset as u16,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(signer.as_ref());
+        data.extend([7u8; ED25519_SIGNATURE_LEN]);
+        data.extend(message);
+        Instruction { program_id: ed25519_program::id(), accounts: vec![], data }
+    }
+
+    fn signed_result() -> SignedPollResult {
+        SignedPollResult {
+            fund: Pubkey::new_unique(),
+            poll_id: 4,
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount: 100,
+            min_amount_out: 90,
+            expires_at: 1_000,
+        }
+    }
+
+    #[test]
+    fn signature_must_be_by_the_oracle_over_the_result() {
+        let oracle = Pubkey::new_unique();
+        let message = signed_result().message().unwrap();
+        assert!(verify_ed25519_instruction(&ed25519_ix(&oracle, &message), &oracle, &message).is_ok());
+        assert!(verify_ed25519_instruction(&ed25519_ix(&Pubkey::new_unique(), &message), &oracle, &message).is_err());
+
+        let mut other = signed_result();
+        other.min_amount_out = 0;
+        let forged = other.message().unwrap();
+        assert!(verify_ed25519_instruction(&ed25519_ix(&oracle, &forged), &oracle, &message).is_err());
+
+        let mut elsewhere = ed25519_ix(&oracle, &message);
+        // Public key read from instruction 0 instead
+        elsewhere.data[ED25519_OFFSETS_START + 6..ED25519_OFFSETS_START + 8].copy_from_slice(&0u16.to_le_bytes());
+        assert!(verify_ed25519_instruction(&elsewhere, &oracle, &message).is_err());
+    }
+
+    #[test]
+    fn poll_results_are_consumed_once_before_expiry() {
+        let signed = signed_result();
+        let mut result = PollResult {
+            fund: signed.fund,
+            poll_id: signed.poll_id,
+            input_mint: signed.input_mint,
+            output_mint: signed.output_mint,
+            amount: signed.amount,
+            min_amount_out: signed.min_amount_out,
+            expires_at: signed.expires_at,
+            consumed: false,
+            bump: 0,
+        };
+        let (input, output) = (signed.input_mint, signed.output_mint);
+        assert!(result.consume(&input, &output, 100, 89, 10).is_err());
+        assert!(result.consume(&input, &output, 100, 90, 1_000).is_err());
+        assert!(result.consume(&input, &output, 100, 90, 10).is_ok());
+        assert!(result.consume(&input, &output, 100, 90, 10).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use anchor_lang::prelude::*;
+
+use crate::nav::pro_rata;
+use crate::{FundDetails, FundError};
+
+pub const POSITION_SEED: &[u8] = b"position";
+
+/// One investor's aggregated stake in one fund, at `[POSITION_SEED, fund, investor]`.
+#[account]
+pub struct InvestorPosition {
+    pub fund: Pubkey,
+    pub investor: Pubkey,
+    pub deposited_lamports: u64, // Lamports deposited for the shares still held
+    pub net_deposited_lamports: u64, // Part of `deposited_lamports` that reached the vault after fees
+    pub shares: u64,
+    pub first_deposit_time: i64,
+    pub last_deposit_time: i64,
+    pub deposit_count: u64,
+    pub bump: u8,
+}
+
+impl InvestorPosition {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 1;
+
+    /// Binds a freshly created position to its fund and investor. No-op once initialized.
+    pub fn init_if_new(&mut self, fund: Pubkey, investor: Pubkey, bump: u8) {
+        if self.fund == Pubkey::default() {
+            self.fund = fund;
+            self.investor = investor;
+            self.bump = bump;
+        }
+    }
+
+    pub fn record_deposit(&mut self, lamports: u64, net_lamports: u64, shares: u64, now: i64) -> Result<()> {
+        self.deposited_lamports = self
+            .deposited_lamports
+            .checked_add(lamports)
+            .ok_or(FundError::OverflowError)?;
+        self.net_deposited_lamports = self
+            .net_deposited_lamports
+            .checked_add(net_lamports)
+            .ok_or(FundError::OverflowError)?;
+        self.shares = self.shares.checked_add(shares).ok_or(FundError::OverflowError)?;
+        if self.deposit_count == 0 {
+            self.first_deposit_time = now;
+        }
+        self.last_deposit_time = now;
+        self.deposit_count = self.deposit_count.checked_add(1).ok_or(FundError::OverflowError)?;
+        Ok(())
+    }
+
+    /// Removes `shares` from the position along with their share of the deposited lamports,
+    /// returning what was released.
+    ///
+    /// Shares can be moved between wallets as plain SPL tokens, so a redeemer may burn more
+    /// than their position recorded; the position then simply bottoms out at zero.
+    pub fn record_redemption(&mut self, shares: u64) -> Result<ReleasedDeposit> {
+        if shares >= self.shares {
+            let released = ReleasedDeposit {
+                lamports: self.deposited_lamports,
+                net_lamports: self.net_deposited_lamports,
+            };
+            self.shares = 0;
+            self.deposited_lamports = 0;
+            self.net_deposited_lamports = 0;
+            return Ok(released);
+        }
+        let released = pro_rata(self.deposited_lamports, shares, self.shares)
+            .ok_or(FundError::OverflowError)?;
+        let released_net = pro_rata(self.net_deposited_lamports, shares, self.shares)
+            .ok_or(FundError::OverflowError)?;
+        self.deposited_lamports = self.deposited_lamports.saturating_sub(released);
+        self.net_deposited_lamports = self.net_deposited_lamports.saturating_sub(released_net);
+        self.shares -= shares;
+        Ok(ReleasedDeposit { lamports: released, net_lamports: released_net })
+    }
+}
+
+/// The part of a position's deposits a redemption released: `lamports` as deposited, fees
+/// included, and `net_lamports` of it that reached the vault.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReleasedDeposit {
+    pub lamports: u64,
+    pub net_lamports: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(fund_id: String, fund_creator: Pubkey, investor: Pubkey)]
+pub struct GetPosition<'info> {
+    #[account(
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_creator.as_ref()],
+        bump,
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        seeds = [POSITION_SEED, fund_details.key().as_ref(), investor.as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Account<'info, InvestorPosition>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refunds_release_exactly_what_deposits_added() {
+        let mut position = InvestorPosition {
+            fund: Pubkey::new_unique(),
+            investor: Pubkey::new_unique(),
+            deposited_lamports: 0,
+            net_deposited_lamports: 0,
+            shares: 0,
+            first_deposit_time: 0,
+            last_deposit_time: 0,
+            deposit_count: 0,
+            bump: 255,
+        };
+        // Two deposits with a 1% entry fee, as `deposit` adds them to `total_deposit`
+        let mut total_deposit: u64 = 0;
+        for (lamports, shares) in [(1_000_000_000, 990), (333_333_333, 329)] {
+            position.record_deposit(lamports, lamports - lamports / 100, shares, 0).unwrap();
+            total_deposit += lamports;
+        }
+
+        // Refunding in uneven parts, as `claim_refund` takes them off again
+        let mut refunded = 0;
+        for shares in [7, 500, 812] {
+            let released = position.record_redemption(shares).unwrap();
+            assert!(released.net_lamports < released.lamports);
+            total_deposit -= released.lamports;
+            refunded += released.net_lamports;
+        }
+        assert_eq!(total_deposit, 0);
+        assert_eq!(refunded, 990_000_000 + 330_000_000);
+        assert_eq!((position.shares, position.deposited_lamports, position.net_deposited_lamports), (0, 0, 0));
+    }
+}
@@ -0,0 +1,387 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::delegation::record_delegated_vetoes;
+use crate::events::{TradeQueued, VetoCast};
+use crate::fees::BPS_DENOMINATOR;
+use crate::governance::{Proposal, PROPOSAL_SEED};
+use crate::nav::{circulating_shares, pro_rata};
+use crate::poll::{PollResult, POLL_RESULT_SEED};
+use crate::position::{InvestorPosition, POSITION_SEED};
+use crate::{FundDetails, FundError};
+
+pub const QUEUED_TRADE_SEED: &[u8] = b"queued_trade";
+pub const VETO_SEED: &[u8] = b"veto";
+// A queued trade that is not executed within this long after its delay becomes stale
+pub const QUEUED_TRADE_EXECUTION_WINDOW_SECONDS: i64 = 60 * 60 * 24 * 3;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum QueuedTradeStatus {
+    /// Waiting out the fund's trade delay; holders may veto it.
+    #[default]
+    Pending,
+    Executed,
+    /// Holders with at least the veto threshold of shares cancelled it.
+    Vetoed,
+}
+
+/// A swap approved by a proposal or poll and waiting out the fund's trade delay, at
+/// `[QUEUED_TRADE_SEED, fund, id]`.
+#[account]
+pub struct QueuedTrade {
+    pub fund: Pubkey,
+    pub id: u64,
+    pub queued_by: Pubkey,
+    pub authorization: Pubkey, // Proposal or poll result that approved the swap
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub min_amount_out: u64,
+    pub queued_at: i64,
+    pub executable_at: i64,
+    pub eligible_shares: u64, // Shares outstanding when queued, the base for the veto threshold
+    pub veto_threshold_bps: u16,
+    pub veto_shares: u64,
+    pub status: QueuedTradeStatus,
+    pub bump: u8,
+}
+
+impl QueuedTrade {
+    pub const LEN: usize = 8 + 32 + 8 + 32 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 2 + 8 + 1 + 1;
+
+    /// Shares that have to veto before the trade is cancelled.
+    pub fn veto_quorum(&self) -> u64 {
+        pro_rata(self.eligible_shares, self.veto_threshold_bps as u64, BPS_DENOMINATOR)
+            .unwrap_or(u64::MAX)
+            .max(1)
+    }
+
+    /// Adds `weight` vetoing shares, cancelling the trade once they reach the threshold.
+    pub fn veto(&mut self, weight: u64, now: i64) -> Result<()> {
+        if self.status != QueuedTradeStatus::Pending || now >= self.executable_at {
+            return err!(FundError::VetoWindowClosed);
+        }
+        self.veto_shares = self.veto_shares.checked_add(weight).ok_or(FundError::OverflowError)?;
+        if self.veto_shares >= self.veto_quorum() {
+            self.status = QueuedTradeStatus::Vetoed;
+        }
+        Ok(())
+    }
+
+    /// Checks the trade is due and matches what was queued, and marks it executed.
+    pub fn execute(
+        &mut self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount: u64,
+        min_amount_out: u64,
+        now: i64,
+    ) -> Result<()> {
+        if self.status != QueuedTradeStatus::Pending {
+            return err!(FundError::TradeNotPending);
+        }
+        if now < self.executable_at {
+            return err!(FundError::TimelockNotElapsed);
+        }
+        if now > self.executable_at.saturating_add(QUEUED_TRADE_EXECUTION_WINDOW_SECONDS) {
+            return err!(FundError::QueuedTradeExpired);
+        }
+        if *input_mint != self.input_mint
+            || *output_mint != self.output_mint
+            || amount != self.amount
+            || min_amount_out < self.min_amount_out
+        {
+            msg!(
+                "Queued {} {} -> {} (min {})",
+                self.amount,
+                self.input_mint,
+                self.output_mint,
+                self.min_amount_out
+            );
+            return err!(FundError::QueuedTradeMismatch);
+        }
+        self.status = QueuedTradeStatus::Executed;
+        Ok(())
+    }
+}
+
+/// One holder's veto of one queued trade, at `[VETO_SEED, queued_trade, holder]`.
+#[account]
+pub struct VetoRecord {
+    pub queued_trade: Pubkey,
+    pub holder: Pubkey,
+    pub cast_by: Pubkey, // The holder, or the delegate who vetoed for them
+    pub weight: u64,
+    pub bump: u8,
+}
+
+impl VetoRecord {
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 1;
+}
+
+/// Uses up an approved proposal or poll result and queues its swap of `source_mint` into
+/// `destination_mint` behind the fund's trade delay. `trade` (SOL into a token),
+/// `usdc_to_sol_trade` (a token into SOL) and `rebalance` (token to token) can only execute
+/// it once the delay has passed without a veto.
+pub fn process_queue_trade(ctx: Context<QueueTrade>, amount: u64, min_amount_out: u64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let (input_mint, output_mint) = (ctx.accounts.source_mint.key(), ctx.accounts.destination_mint.key());
+    let authorization = match (&mut ctx.accounts.proposal, &mut ctx.accounts.poll_result) {
+        (Some(proposal), None) => {
+            proposal.execute(&input_mint, &output_mint, amount, min_amount_out, now)?;
+            proposal.key()
+        }
+        (None, Some(poll_result)) => {
+            poll_result.consume(&input_mint, &output_mint, amount, min_amount_out, now)?;
+            poll_result.key()
+        }
+        _ => return err!(FundError::TradeNotAuthorized),
+    };
+
+    let fund_key = ctx.accounts.fund_details.key();
+    let governance = ctx.accounts.fund_details.governance;
+    let queued = &mut ctx.accounts.queued_trade;
+    queued.fund = fund_key;
+    queued.id = ctx.accounts.fund_details.queued_trade_count;
+    queued.queued_by = ctx.accounts.trader.key();
+    queued.authorization = authorization;
+    queued.input_mint = input_mint;
+    queued.output_mint = output_mint;
+    queued.amount = amount;
+    queued.min_amount_out = min_amount_out;
+    queued.queued_at = now;
+    queued.executable_at = now.checked_add(governance.trade_delay).ok_or(FundError::OverflowError)?;
+    queued.eligible_shares = circulating_shares(
+        ctx.accounts.fund_token_mint.supply,
+        ctx.accounts.fund_token_account.amount,
+    );
+    queued.veto_threshold_bps = governance.veto_threshold_bps;
+    queued.veto_shares = 0;
+    queued.status = QueuedTradeStatus::Pending;
+    queued.bump = *ctx.bumps.get("queued_trade").unwrap();
+
+    let fund_details = &mut ctx.accounts.fund_details;
+    fund_details.queued_trade_count = fund_details
+        .queued_trade_count
+        .checked_add(1)
+        .ok_or(FundError::OverflowError)?;
+
+    msg!("Trade {} queued, executable from {}", queued.id, queued.executable_at);
+    emit_cpi!(TradeQueued {
+        fund: fund_key,
+        queued_trade: ctx.accounts.queued_trade.key(),
+        id: ctx.accounts.queued_trade.id,
+        authorization,
+        input_mint,
+        output_mint,
+        amount,
+        min_amount_out,
+        executable_at: ctx.accounts.queued_trade.executable_at,
+    });
+    Ok(())
+}
+
+/// Vetoes a pending trade with the holder's shares, plus those of every holder delegating to
+/// them whose accounts are passed in `remaining_accounts` (see `record_delegated_vetoes`).
+/// Shares are counted the same way as votes.
+pub fn process_veto_trade<'info>(ctx: Context<'_, '_, '_, 'info, VetoTrade<'info>>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    // A delegate without shares of their own passes neither account
+    let own_weight = match (&ctx.accounts.position, &ctx.accounts.holder_token_account) {
+        (Some(position), Some(token_account)) => position.shares.min(token_account.amount),
+        _ => 0,
+    };
+    let delegated_weight = record_delegated_vetoes(
+        ctx.program_id,
+        &ctx.accounts.fund_details,
+        &ctx.accounts.fund_details.key(),
+        &ctx.accounts.queued_trade.key(),
+        &ctx.accounts.holder.to_account_info(),
+        ctx.remaining_accounts,
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+    let weight = own_weight.checked_add(delegated_weight).ok_or(FundError::OverflowError)?;
+    if weight == 0 {
+        return err!(FundError::NoVotingPower);
+    }
+
+    let queued = &mut ctx.accounts.queued_trade;
+    queued.veto(weight, now)?;
+
+    let veto = &mut ctx.accounts.veto_record;
+    veto.queued_trade = queued.key();
+    veto.holder = ctx.accounts.holder.key();
+    veto.cast_by = ctx.accounts.holder.key();
+    veto.weight = own_weight;
+    veto.bump = *ctx.bumps.get("veto_record").unwrap();
+
+    msg!(
+        "Veto with {} shares ({} delegated), {} of {} needed",
+        weight,
+        delegated_weight,
+        queued.veto_shares,
+        queued.veto_quorum()
+    );
+    emit_cpi!(VetoCast {
+        queued_trade: ctx.accounts.queued_trade.key(),
+        holder: ctx.accounts.holder.key(),
+        weight,
+        delegated_weight,
+        veto_shares: ctx.accounts.queued_trade.veto_shares,
+        vetoed: ctx.accounts.queued_trade.status == QueuedTradeStatus::Vetoed,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct QueueTrade<'info> {
+    #[account(
+        mut,
+        has_one = fund_token_mint,
+        constraint = fund_details.roles.is_trader(&trader.key()) @ FundError::UnauthorizedTrader,
+        constraint = fund_details.effective_status(Clock::get()?.unix_timestamp).allows_trading() @ FundError::InvalidFundStatus
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        init,
+        payer = trader,
+        space = QueuedTrade::LEN,
+        seeds = [QUEUED_TRADE_SEED, fund_details.key().as_ref(), &fund_details.queued_trade_count.to_le_bytes()],
+        bump
+    )]
+    pub queued_trade: Account<'info, QueuedTrade>,
+
+    /// Approved proposal to queue; pass either this or `poll_result`
+    #[account(
+        mut,
+        seeds = [PROPOSAL_SEED, fund_details.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
+    /// Verified Telegram poll result to queue
+    #[account(
+        mut,
+        seeds = [POLL_RESULT_SEED, fund_details.key().as_ref(), &poll_result.poll_id.to_le_bytes()],
+        bump = poll_result.bump
+    )]
+    pub poll_result: Option<Account<'info, PollResult>>,
+
+    /// Mint the swap spends, the native mint for SOL
+    pub source_mint: Account<'info, Mint>,
+
+    pub destination_mint: Account<'info, Mint>,
+
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = fund_details
+    )]
+    pub fund_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub trader: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct VetoTrade<'info> {
+    #[account(has_one = fund_token_mint)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        mut,
+        seeds = [QUEUED_TRADE_SEED, fund_details.key().as_ref(), &queued_trade.id.to_le_bytes()],
+        bump = queued_trade.bump
+    )]
+    pub queued_trade: Account<'info, QueuedTrade>,
+
+    #[account(
+        init,
+        payer = holder,
+        space = VetoRecord::LEN,
+        seeds = [VETO_SEED, queued_trade.key().as_ref(), holder.key().as_ref()],
+        bump
+    )]
+    pub veto_record: Account<'info, VetoRecord>,
+
+    #[account(
+        seeds = [POSITION_SEED, fund_details.key().as_ref(), holder.key().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Option<Account<'info, InvestorPosition>>,
+
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = holder
+    )]
+    pub holder_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued() -> QueuedTrade {
+        QueuedTrade {
+            fund: Pubkey::default(),
+            id: 0,
+            queued_by: Pubkey::default(),
+            authorization: Pubkey::default(),
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount: 100,
+            min_amount_out: 90,
+            queued_at: 0,
+            executable_at: 100,
+            eligible_shares: 1_000,
+            veto_threshold_bps: 1_000,
+            veto_shares: 0,
+            status: QueuedTradeStatus::Pending,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn trades_wait_out_the_delay() {
+        let mut trade = queued();
+        let (input, output) = (trade.input_mint, trade.output_mint);
+        assert!(trade.execute(&input, &output, 100, 90, 99).is_err());
+        assert!(trade.execute(&input, &output, 100, 89, 100).is_err());
+        assert!(trade.execute(&input, &output, 100, 90, 100).is_ok());
+        assert_eq!(trade.status, QueuedTradeStatus::Executed);
+        assert!(trade.execute(&input, &output, 100, 90, 100).is_err());
+
+        let mut stale = queued();
+        let late = 100 + QUEUED_TRADE_EXECUTION_WINDOW_SECONDS + 1;
+        assert!(stale.execute(&input, &output, 100, 90, late).is_err());
+    }
+
+    #[test]
+    fn vetoes_cancel_at_the_threshold() {
+        let mut trade = queued();
+        let (input, output) = (trade.input_mint, trade.output_mint);
+        trade.veto(60, 10).unwrap();
+        assert_eq!(trade.status, QueuedTradeStatus::Pending);
+        trade.veto(40, 10).unwrap();
+        assert_eq!(trade.status, QueuedTradeStatus::Vetoed);
+        assert!(trade.execute(&input, &output, 100, 90, 100).is_err());
+
+        // No vetoes once the delay has passed
+        let mut due = queued();
+        assert!(due.veto(500, 100).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+use crate::allowlist::{AssetAllowlist, ALLOWLIST_SEED};
+use crate::events::TradeExecuted;
+use crate::ledger::{TradeLedger, TradeRecord, TRADE_LEDGER_SEED};
+use crate::oracle::{Oracle, PriceFeed, PRICE_FEED_SEED};
+use crate::queue::{QueuedTrade, QUEUED_TRADE_SEED};
+use crate::swap::{
+    check_min_amount_out, check_route_accounts, check_swap_effects, init_fund_token_account, swap_on_jupiter,
+    token_balance, Jupiter, SwapAmounts,
+};
+use crate::{FundDetails, FundError, AUTHORITY_SEED, FUND_TOKEN_SEED};
+
+/// Swaps `amount_in` of one fund holding into another through the swap CPI, carrying out a
+/// queued trade whose delay has passed without a veto.
+///
+/// Both sides are the fund's own `FUND_TOKEN_SEED` accounts, owned by the fund's authority.
+/// The authority signs the route, so the route may not write to any other account the
+/// authority owns, nor to the authority itself; the source may lose at most `amount_in`, and
+/// afterwards both sides must still be owned by the authority with no delegate or close
+/// authority set.
+pub fn process_rebalance(
+    ctx: Context<Rebalance>,
+    fund_id: String,
+    amount_in: u64,
+    min_amount_out: u64,
+    data: Vec<u8>,
+) -> Result<()> {
+    msg!("Rebalance instruction started for fund_id: {}", fund_id);
+    let now = Clock::get()?.unix_timestamp;
+
+    // The fund may only buy tokens the oracle can price, otherwise NAV breaks
+    let oracle = Oracle::load(
+        ctx.program_id,
+        &[
+            ctx.accounts.destination_price_feed.to_account_info(),
+            ctx.accounts.destination_feed.to_account_info(),
+        ],
+        now,
+    )?;
+    oracle.price_of(&ctx.accounts.destination_mint.key())?;
+
+    ctx.accounts.queued_trade.execute(
+        &ctx.accounts.source_mint.key(),
+        &ctx.accounts.destination_mint.key(),
+        amount_in,
+        min_amount_out,
+        now,
+    )?;
+    msg!("Executing queued trade {}", ctx.accounts.queued_trade.id);
+
+    let source_before = ctx.accounts.source_token_account.amount;
+    if source_before < amount_in {
+        msg!("Fund holds {} tokens, rebalance needs {}", source_before, amount_in);
+        return err!(FundError::InsufficientFunds);
+    }
+
+    let fund_key = ctx.accounts.fund_details.key();
+    let destination_mint_key = ctx.accounts.destination_mint.key();
+    let authority_bump = *ctx.bumps.get("program_authority").unwrap();
+    let destination_bump = *ctx.bumps.get("destination_token_account").unwrap();
+    let authority_seeds: &[&[u8]] = &[AUTHORITY_SEED, fund_key.as_ref(), &[authority_bump]];
+    let destination_seeds: &[&[u8]] = &[
+        FUND_TOKEN_SEED,
+        fund_key.as_ref(),
+        destination_mint_key.as_ref(),
+        &[destination_bump],
+    ];
+
+    // The trader pays rent for a destination the fund has never held before
+    init_fund_token_account(
+        &ctx.accounts.destination_token_account,
+        &ctx.accounts.destination_mint.to_account_info(),
+        &ctx.accounts.program_authority.to_account_info(),
+        &ctx.accounts.user_account.to_account_info(),
+        &ctx.accounts.token_program,
+        &ctx.accounts.system_program,
+        &[destination_seeds],
+    )?;
+
+    let destination_before = token_balance(&ctx.accounts.destination_token_account)?;
+
+    let authority_key = ctx.accounts.program_authority.key();
+    check_route_accounts(
+        ctx.remaining_accounts,
+        &authority_key,
+        &[ctx.accounts.source_token_account.key(), ctx.accounts.destination_token_account.key()],
+    )?;
+    swap_on_jupiter(
+        ctx.remaining_accounts,
+        ctx.accounts.jupiter_program.clone(),
+        &ctx.accounts.program_authority,
+        data,
+        authority_seeds,
+    )?;
+
+    // The authority signs the whole swap, so also make sure it spent no more than asked
+    ctx.accounts.source_token_account.reload()?;
+    let destination_after = token_balance(&ctx.accounts.destination_token_account)?;
+    let destination = TokenAccount::try_deserialize(&mut ctx.accounts.destination_token_account.try_borrow_data()?.as_ref())?;
+    let spent = check_swap_effects(
+        source_before,
+        ctx.accounts.source_token_account.amount,
+        amount_in,
+        &[&ctx.accounts.source_token_account, &destination],
+        &authority_key,
+    )?;
+    let received = check_min_amount_out(destination_before, destination_after, min_amount_out)?;
+
+    msg!(
+        "Rebalanced {} {} into {} {}",
+        spent,
+        ctx.accounts.source_mint.key(),
+        received,
+        destination_mint_key
+    );
+
+    ctx.accounts.fund_details.record_trade(destination_mint_key, now)?;
+    let swapped = SwapAmounts { amount_in: spent, amount_out: received };
+    ctx.accounts.trade_ledger.record(TradeRecord::new(
+        ctx.accounts.source_mint.key(),
+        destination_mint_key,
+        swapped,
+        ctx.accounts.queued_trade.authorization,
+    )?)?;
+
+    emit_cpi!(TradeExecuted {
+        fund: fund_key,
+        trader: ctx.accounts.user_account.key(),
+        input_mint: ctx.accounts.source_mint.key(),
+        output_mint: destination_mint_key,
+        amount_in: spent,
+        amount_out: received,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(fund_id: String)]
+pub struct Rebalance<'info> {
+    #[account(
+        mut,
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_details.authority.as_ref()],
+        bump,
+        constraint = fund_details.roles.is_trader(&user_account.key()) @ FundError::UnauthorizedTrader,
+        constraint = fund_details.effective_status(Clock::get()?.unix_timestamp).allows_trading() @ FundError::InvalidFundStatus
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
+    pub program_authority: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+
+    #[account(
+        constraint = fund_details.holdings.contains(&source_mint.key()) @ FundError::HoldingAccountMismatch,
+        constraint = asset_allowlist.is_allowed(&source_mint.key()) @ FundError::AssetNotAllowed
+    )]
+    pub source_mint: Account<'info, Mint>,
+
+    #[account(
+        constraint = destination_mint.key() != source_mint.key() @ FundError::InvalidRebalancePair,
+        constraint = asset_allowlist.is_allowed(&destination_mint.key()) @ FundError::AssetNotAllowed
+    )]
+    pub destination_mint: Account<'info, Mint>,
+
+    #[account(seeds = [ALLOWLIST_SEED, fund_details.key().as_ref()], bump = asset_allowlist.bump)]
+    pub asset_allowlist: Account<'info, AssetAllowlist>,
+
+    #[account(
+        mut,
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), source_mint.key().as_ref()],
+        bump,
+        token::mint = source_mint,
+        token::authority = program_authority
+    )]
+    pub source_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Fund token account for the destination mint, created on first use and
+    /// otherwise checked to belong to `program_authority`
+    #[account(
+        mut,
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), destination_mint.key().as_ref()],
+        bump
+    )]
+    pub destination_token_account: UncheckedAccount<'info>,
+
+    #[account(seeds = [PRICE_FEED_SEED, destination_mint.key().as_ref()], bump = destination_price_feed.bump)]
+    pub destination_price_feed: Account<'info, PriceFeed>,
+
+    /// CHECK: Price account registered for the destination mint, parsed by the oracle
+    #[account(address = destination_price_feed.feed @ FundError::PriceFeedMismatch)]
+    pub destination_feed: UncheckedAccount<'info>,
+
+    #[account(mut, seeds = [TRADE_LEDGER_SEED, fund_details.key().as_ref()], bump = trade_ledger.bump)]
+    pub trade_ledger: Account<'info, TradeLedger>,
+
+    /// Queued swap this trade carries out
+    #[account(
+        mut,
+        seeds = [QUEUED_TRADE_SEED, fund_details.key().as_ref(), &queued_trade.id.to_le_bytes()],
+        bump = queued_trade.bump
+    )]
+    pub queued_trade: Account<'info, QueuedTrade>,
+
+    pub jupiter_program: Program<'info, Jupiter>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
@@ -1,219 +1,203 @@
 // Rust implementation (redeem.rs)
 use anchor_lang::prelude::*;
-use anchor_spl::{associated_token::AssociatedToken, token::{self, Approve, Burn, Mint, Token, TokenAccount, Transfer}};
-use crate::FundDetails;
-use solana_program::{
-    native_token::LAMPORTS_PER_SOL, program::{invoke, invoke_signed}, system_instruction,
-    clock::Clock
-};
+use anchor_spl::{associated_token::AssociatedToken, token::{self, Burn, Mint, Token, TokenAccount}};
+use crate::events::Redeemed;
+use crate::fees::mint_fee_shares;
+use crate::nav::{circulating_shares, compute_nav, load_nav_accounts, pro_rata};
+use crate::fee_vault::{FeeVault, MANAGER_FEE_VAULT_SEED, PROTOCOL_FEE_VAULT_SEED};
+use crate::lifecycle::FundStatus;
+use crate::position::{InvestorPosition, POSITION_SEED};
+use crate::{FundDetails, VAULT_SEED};
+use solana_program::{program::invoke_signed, system_instruction, clock::Clock};
 
-// Corrected redeem.rs with balanced lamport transfers
-
-pub fn process_redeem(ctx: Context<RedeemLiquidity>, amount: u64, fund_id: String, fund_creator: Pubkey, manager_address: Pubkey) -> Result<()> {
+pub fn process_redeem(ctx: Context<RedeemLiquidity>, amount: u64, fund_id: String, fund_creator: Pubkey, _manager_address: Pubkey) -> Result<()> {
     msg!("=== REDEEM PROCESS STARTED ===");
-    
-    // Import fund status constants
-    use crate::{FUND_STATUS_ACTIVE, FUND_STATUS_TRADE, FUND_STATUS_EXPIRED, THREE_MONTHS_SECONDS, FundError};
-    
-    // Check fund expiration status
-    let current_time = Clock::get()?.unix_timestamp;
-    let fund_start_time = ctx.accounts.fund_details.start_time;
-    let fund_age = current_time - fund_start_time;
-    
-    // Update status to expired if needed
-    if fund_age > THREE_MONTHS_SECONDS && ctx.accounts.fund_details.status != FUND_STATUS_EXPIRED {
-        ctx.accounts.fund_details.status = FUND_STATUS_EXPIRED;
-        msg!("Fund has expired due to age over 3 months");
-    }
-    
-    // Burn the user's tokens first
-    let burn_ctx = CpiContext::new(
-        ctx.accounts.token_program.to_account_info(),
-        Burn {
-            mint: ctx.accounts.token_mint.to_account_info(),
-            from: ctx.accounts.user_token_account.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        },
-    );
-    
-    token::burn(burn_ctx, amount)?;
-    
-    let user_token_balance = amount;
-    msg!("User's after token balance: {}", user_token_balance);
-    
-    if user_token_balance == 0 {
+
+    use crate::FundError;
+
+    if amount == 0 {
         return err!(FundError::InsufficientTokens);
     }
-    
-    let sol_amount = user_token_balance;
-    
-    // Calculate fee correctly to avoid overflow
-    // Instead of dividing first (which can cause precision loss), we'll multiply by the percentage first
-    let total_fee = sol_amount.checked_mul(1).ok_or(FundError::OverflowError)?.checked_div(100).ok_or(FundError::OverflowError)?;
-    
-    // Ensure we don't overflow by checking each calculation
-    let user_receives = sol_amount.checked_sub(total_fee).ok_or(FundError::OverflowError)?;
-    
-    // Split fees - use saturating operations to prevent overflow
-    let manager_fee = total_fee.saturating_mul(20).saturating_div(100);
-    let owner_fee = total_fee.saturating_sub(manager_fee);
-    
-    // Verify fund has enough SOL
-    let fund_lamports = ctx.accounts.fund_details.to_account_info().lamports();
-    msg!("fund_lamports sol_amount {:?} {:?}", fund_lamports, sol_amount);
-    
-    if fund_lamports < sol_amount {
-        return err!(FundError::InsufficientFunds);
-    }
-    
-    // Defensive programming: ensure user_receives doesn't exceed fund_lamports
-    if user_receives > fund_lamports {
-        return err!(FundError::OverflowError);
+
+    // Expiry is tracked by `crank_lifecycle`; redemptions stay open in every status
+    let current_time = Clock::get()?.unix_timestamp;
+
+    let expired = ctx.accounts.fund_details.status == FundStatus::Expired;
+    let (sol_amount, nav, outstanding_shares, fee_shares) = if expired {
+        // Everything has been liquidated to SOL: pay out of the final distribution snapshot
+        let fund_details = &ctx.accounts.fund_details;
+        if amount > fund_details.final_distribution_shares || amount > ctx.accounts.user_token_account.amount {
+            return err!(FundError::InsufficientTokens);
+        }
+        let sol_amount =
+            pro_rata(amount, fund_details.final_distribution_lamports, fund_details.final_distribution_shares)
+                .ok_or(FundError::OverflowError)?;
+        (sol_amount, fund_details.final_distribution_lamports, fund_details.final_distribution_shares, 0)
+    } else {
+        // Price the shares at the current NAV, before anything is burned or paid out
+        let (holding_accounts, oracle) = load_nav_accounts(
+            ctx.program_id,
+            &ctx.accounts.fund_details,
+            ctx.remaining_accounts,
+            current_time,
+        )?;
+        let nav = compute_nav(
+            ctx.program_id,
+            &ctx.accounts.fund_details,
+            &ctx.accounts.fund_details.key(),
+            ctx.accounts.fund_vault.lamports(),
+            holding_accounts,
+            &oracle,
+        )?;
+        let mut outstanding_shares = circulating_shares(
+            ctx.accounts.fund_token_mint.supply,
+            ctx.accounts.fund_token_account.amount,
+        );
+        msg!("NAV: {} lamports, outstanding shares: {}", nav, outstanding_shares);
+
+        // Settle management and performance fees before pricing the redemption, so the
+        // leaving investor pays their part of them
+        let fee_shares = ctx.accounts.fund_details.settle_fees(nav, outstanding_shares, current_time)?;
+        let fund_details_bump = *ctx.bumps.get("fund_details").unwrap();
+        let fund_details_seeds: &[&[u8]] = &[
+            b"fund_details",
+            fund_id.as_bytes(),
+            fund_creator.as_ref(),
+            &[fund_details_bump],
+        ];
+        mint_fee_shares(
+            &ctx.accounts.fund_details,
+            fund_details_seeds,
+            &ctx.accounts.fund_token_mint,
+            &ctx.accounts.manager_token_account,
+            &ctx.accounts.token_program,
+            fee_shares,
+        )?;
+        outstanding_shares = outstanding_shares.checked_add(fee_shares).ok_or(FundError::OverflowError)?;
+        let fund_details = &mut ctx.accounts.fund_details;
+        fund_details.tokens_minted = fund_details.tokens_minted.checked_add(fee_shares)
+            .ok_or(FundError::OverflowError)?;
+
+        if amount > outstanding_shares || amount > ctx.accounts.user_token_account.amount {
+            return err!(FundError::InsufficientTokens);
+        }
+
+        let sol_amount = pro_rata(amount, nav, outstanding_shares).ok_or(FundError::OverflowError)?;
+        (sol_amount, nav, outstanding_shares, fee_shares)
+    };
+    if sol_amount == 0 {
+        return err!(FundError::InvalidWithdrawAmount);
     }
-    
-    // Transfer SOL using the explicit try_borrow_mut_lamports approach
-    // IMPORTANT: Make sure we have enough lamports for rent exemption
-    let min_rent = Rent::get()?.minimum_balance(ctx.accounts.fund_details.to_account_info().data_len());
-    
-    // Ensure we don't drop below rent exemption requirement
-    if fund_lamports.saturating_sub(user_receives) < min_rent {
+
+    // Redemptions are paid in SOL, so the vault must hold enough liquid SOL to cover them.
+    // The vault keeps its rent-exempt reserve, so that part is never redeemable.
+    let vault_reserve = Rent::get()?.minimum_balance(0);
+    let vault_liquidity = ctx.accounts.fund_vault.lamports().saturating_sub(vault_reserve);
+    if sol_amount > vault_liquidity {
+        msg!("Vault holds {} lamports of SOL, redemption needs {}", vault_liquidity, sol_amount);
         return err!(FundError::InsufficientFunds);
     }
-    
-    // Transfer the exact amount user should receive
-    **ctx.accounts.fund_details.to_account_info().try_borrow_mut_lamports()? = 
-        fund_lamports.checked_sub(user_receives).ok_or(FundError::OverflowError)?;
-    
-    let user_lamports = ctx.accounts.user.to_account_info().lamports();
-    **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? = 
-        user_lamports.checked_add(user_receives).ok_or(FundError::OverflowError)?;
-    
-    msg!("after transfer");
-    
-    // Update fund manager state
+
+    // Burn the user's shares
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.fund_token_mint.to_account_info(),
+                from: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    // Redemption fee, split between the manager and the protocol per the fund's schedule
+    let fees = ctx.accounts.fund_details.fee_schedule.redemption_fees(sol_amount)?;
+    let user_receives = fees.net;
+    let manager_fee = fees.manager_fee;
+    let owner_fee = fees.protocol_fee;
+
+    // Pay everything out of the fund vault
+    let vault_bump = *ctx.bumps.get("fund_vault").unwrap();
+    let fund_key = ctx.accounts.fund_details.key();
+    let vault_seeds: &[&[u8]] = &[VAULT_SEED, fund_key.as_ref(), &[vault_bump]];
+
+    transfer_from_vault(&ctx, ctx.accounts.user.to_account_info(), user_receives, vault_seeds)?;
+    transfer_from_vault(&ctx, ctx.accounts.manager_fee_vault.to_account_info(), manager_fee, vault_seeds)?;
+    transfer_from_vault(&ctx, ctx.accounts.protocol_fee_vault.to_account_info(), owner_fee, vault_seeds)?;
+
+    // Both fee vaults only count what has actually been paid into them
+    let fee_recipient = ctx.accounts.fee_recipient.key();
+    let manager_vault_bump = *ctx.bumps.get("manager_fee_vault").unwrap();
+    let manager_fee_vault = &mut ctx.accounts.manager_fee_vault;
+    manager_fee_vault.init_if_new(fee_recipient, manager_vault_bump);
+    manager_fee_vault.record_fee(manager_fee)?;
+    let protocol_vault_bump = *ctx.bumps.get("protocol_fee_vault").unwrap();
+    let protocol_fee_vault = &mut ctx.accounts.protocol_fee_vault;
+    protocol_fee_vault.init_if_new(Pubkey::default(), protocol_vault_bump);
+    protocol_fee_vault.record_fee(owner_fee)?;
+
+    // Update fund accounting
     let fund_details = &mut ctx.accounts.fund_details;
-    
-    // Make sure these operations can't underflow
-    // fund_details.tokens_minted = fund_details.tokens_minted
-    //     .checked_sub(user_token_balance)
-    //     .ok_or(FundError::OverflowError)?;
-    
-    // fund_details.total_deposit = fund_details.total_deposit
-    //     .checked_sub(sol_amount)
-    //     .ok_or(FundError::OverflowError)?;
-    
+    fund_details.tokens_minted = fund_details.tokens_minted.saturating_sub(amount);
+    fund_details.total_deposit = fund_details.total_deposit.saturating_sub(sol_amount);
+    fund_details.current_depost = fund_details.current_depost.saturating_sub(sol_amount);
+    if expired {
+        fund_details.final_distribution_lamports = fund_details.final_distribution_lamports.saturating_sub(sol_amount);
+        fund_details.final_distribution_shares = fund_details.final_distribution_shares.saturating_sub(amount);
+    }
+
+    let fund_key = fund_details.key();
+    let investor = ctx.accounts.user.key();
+    let position_bump = *ctx.bumps.get("position").unwrap();
+    let position = &mut ctx.accounts.position;
+    position.init_if_new(fund_key, investor, position_bump);
+    position.record_redemption(amount)?;
+
     msg!("=== REDEEM PROCESS COMPLETED ===");
-    msg!("Tokens burned: {}", user_token_balance);
+    msg!("Tokens burned: {}", amount);
     msg!("SOL returned to user: {}", user_receives);
     msg!("Manager fee: {}", manager_fee);
-    
+    msg!("Owner fee: {}", owner_fee);
+
+    emit_cpi!(Redeemed {
+        fund: fund_key,
+        investor,
+        shares: amount,
+        gross_amount: sol_amount,
+        net_amount: user_receives,
+        manager_fee,
+        protocol_fee: owner_fee,
+        fee_shares,
+        nav,
+        outstanding_shares: outstanding_shares.saturating_sub(amount),
+        timestamp: current_time,
+    });
     Ok(())
 }
 
-// pub fn process_redeem(ctx: Context<RedeemLiquidity>, amount: u64, fund_id: String, fund_creator: Pubkey,manager_address:Pubkey) -> Result<()> {
-//     msg!("=== REDEEM PROCESS STARTED ===");
-    
-//     // Burn the user's tokens first
-//     let burn_ctx = CpiContext::new(
-//         ctx.accounts.token_program.to_account_info(),
-//         Burn {
-//             mint: ctx.accounts.token_mint.to_account_info(),
-//             from: ctx.accounts.user_token_account.to_account_info(),
-//             authority: ctx.accounts.user.to_account_info(),
-//         },
-//     );
-    
-//     token::burn(burn_ctx, amount)?;
-    
-//     let user_token_balance = amount;
-//     msg!("User's after token balance: {}", user_token_balance);
-    
-//     if user_token_balance == 0 {
-//         return err!(ErrorCode::InsufficientTokens);
-//     }
-    
-//     let sol_amount = user_token_balance;
-//     let total_fee = sol_amount.checked_div(100).ok_or(ErrorCode::OverflowError)?;
-//     let user_receives = sol_amount.checked_sub(total_fee).ok_or(ErrorCode::OverflowError)?;
-    
-//     // Split fees
-//     let manager_fee = total_fee.checked_mul(20).ok_or(ErrorCode::OverflowError)? / 100;
-//     let owner_fee = total_fee.checked_sub(manager_fee).ok_or(ErrorCode::OverflowError)?;
-    
-//     // Verify fund has enough SOL
-//     let fund_lamports = ctx.accounts.fund_details.to_account_info().lamports();
-//     msg!("fund_lamports sol_amount {:?} {:?}", fund_lamports, sol_amount);
-//     if fund_lamports < sol_amount {
-//         return err!(ErrorCode::InsufficientFunds);
-//     }
-    
-//     // Transfer the exact amount user should receive (user_receives instead of sol_amount)
-//     **ctx.accounts.fund_details.to_account_info().try_borrow_mut_lamports()? = 
-//         fund_lamports.checked_sub(user_receives).ok_or(ErrorCode::OverflowError)?;
-    
-//     let user_lamports = ctx.accounts.user.to_account_info().lamports();
-//     **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? = 
-//         user_lamports.checked_add(user_receives).ok_or(ErrorCode::OverflowError)?;
-    
-//     msg!("after transfer");
-    
-//     // Handle the fee transfer separately using the system program
-//     // No need to do a separate transfer from user to creator for the fee
-//     // because you never transferred that portion to the user
-    
-//     // If you want the fund creator to get their fee, transfer directly from fund to creator
-//     // let creator_lamports = ctx.accounts.fund_creator_account.lamports();
-//     // **ctx.accounts.fund_creator_account.try_borrow_mut_lamports()? = 
-//     //     creator_lamports.checked_add(manager_fee).ok_or(ErrorCode::OverflowError)?;
-        
-//     // // Reduce the fund balance by the manager fee
-//     // **ctx.accounts.fund_details.to_account_info().try_borrow_mut_lamports()? = 
-//     //     ctx.accounts.fund_details.to_account_info().lamports().checked_sub(manager_fee).ok_or(ErrorCode::OverflowError)?;
-    
-
-//    // Transfer hedge owner fee
-// //    let owner_lamports = ctx.accounts.hedge_fund_owner.lamports();
-// //    **ctx.accounts.hedge_fund_owner.try_borrow_mut_lamports()? = 
-// //        owner_lamports.checked_add(owner_fee).ok_or(ErrorCode::OverflowError)?;
-       
-// //    // Reduce the fund balance by the manager fee
-// //    **ctx.accounts.fund_details.to_account_info().try_borrow_mut_lamports()? = 
-// //        ctx.accounts.fund_details.to_account_info().lamports().checked_sub(owner_fee).ok_or(ErrorCode::OverflowError)?;
-
-//     // Transfer hedge owner fee
-//     // invoke(
-//     //     &system_instruction::transfer(
-//     //         &ctx.accounts.user.key(),
-//     //         &ctx.accounts.hedge_fund_owner.key(),
-//     //         owner_fee,
-//     //     ),
-//     //     &[
-//     //         ctx.accounts.user.to_account_info(),
-//     //         ctx.accounts.hedge_fund_owner.to_account_info(),
-//     //         ctx.accounts.system_program.to_account_info(),
-//     //     ],
-//     // )?;
-
-    
-//     // Update fund manager state
-//     let fund_details = &mut ctx.accounts.fund_details;
-//     fund_details.tokens_minted = fund_details.tokens_minted
-//         .checked_sub(user_token_balance)
-//         .ok_or(ErrorCode::OverflowError)?;
-    
-//     fund_details.total_deposit = fund_details.total_deposit
-//         .checked_sub(sol_amount)
-//         .ok_or(ErrorCode::OverflowError)?;
-    
-//     msg!("=== REDEEM PROCESS COMPLETED ===");
-//     msg!("Tokens burned: {}", user_token_balance);
-//     msg!("SOL returned to user: {}", user_receives);
-//     msg!("Manager fee: {}", manager_fee);
-    
-//     Ok(())
-// }
-
+fn transfer_from_vault<'info>(
+    ctx: &Context<RedeemLiquidity<'info>>,
+    to: AccountInfo<'info>,
+    lamports: u64,
+    vault_seeds: &[&[u8]],
+) -> Result<()> {
+    if lamports == 0 {
+        return Ok(());
+    }
+    invoke_signed(
+        &system_instruction::transfer(&ctx.accounts.fund_vault.key(), to.key, lamports),
+        &[
+            ctx.accounts.fund_vault.to_account_info(),
+            to,
+            ctx.accounts.system_program.to_account_info(),
+        ],
+        &[vault_seeds],
+    )?;
+    Ok(())
+}
 
+#[event_cpi]
 #[derive(Accounts)]
 #[instruction(amount: u64, fund_id: String,fund_creator: Pubkey,manager_address:Pubkey)]
 pub struct RedeemLiquidity<'info> {
@@ -227,17 +211,34 @@ pub struct RedeemLiquidity<'info> {
 
     #[account(
         mut,
+        seeds = [VAULT_SEED, fund_details.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a simple SOL vault owned by the system program
+    pub fund_vault: UncheckedAccount<'info>,
+
+    #[account(
         associated_token::mint = fund_token_mint,
         associated_token::authority = fund_details
     )]
     pub fund_token_account: Account<'info, TokenAccount>,
- /// CHECK: This is the hedge fund owner account
     #[account(
-        mut, 
-        seeds = [b"fund_manager", manager_address.as_ref()],
+        init_if_needed,
+        payer = user,
+        space = FeeVault::LEN,
+        seeds = [MANAGER_FEE_VAULT_SEED, fund_details.roles.fee_recipient.as_ref()],
+        bump
+    )]
+    pub manager_fee_vault: Account<'info, FeeVault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = FeeVault::LEN,
+        seeds = [PROTOCOL_FEE_VAULT_SEED],
         bump
     )]
-    pub hedge_fund_owner: AccountInfo<'info>,
+    pub protocol_fee_vault: Account<'info, FeeVault>,
 
     #[account(
         mut,
@@ -246,20 +247,34 @@ pub struct RedeemLiquidity<'info> {
     )]
     pub user_token_account: Account<'info, TokenAccount>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = InvestorPosition::LEN,
+        seeds = [POSITION_SEED, fund_details.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, InvestorPosition>,
+
     #[account(mut)]
     pub user: Signer<'info>,
-    /// CHECK: This is the fund creator's account to receive fees
-    #[account(mut, constraint = fund_creator_account.key() == fund_creator)]
-    pub fund_creator_account: AccountInfo<'info>,
+    /// CHECK: The fund's fee recipient, owner of the manager's fee vault and fee shares
+    #[account(address = fund_details.roles.fee_recipient)]
+    pub fee_recipient: AccountInfo<'info>,
 
-    #[account(mut)]
-    pub token_mint:Account<'info, Mint>,
+    // Receives performance fee shares
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = fee_recipient,
+    )]
+    pub manager_token_account: Account<'info, TokenAccount>,
 
+    #[account(mut)]
     pub fund_token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
-
-// ErrorCode enum removed, using FundError from lib.rs instead
\ No newline at end of file
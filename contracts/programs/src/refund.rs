@@ -0,0 +1,123 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{associated_token::AssociatedToken, token::{self, Burn, Mint, Token, TokenAccount}};
+use solana_program::{program::invoke_signed, system_instruction};
+
+use crate::events::RefundClaimed;
+use crate::position::{InvestorPosition, POSITION_SEED};
+use crate::{FundDetails, FundError, VAULT_SEED};
+
+/// Returns an investor's net deposit once a fundraise has missed its deadline below
+/// `invest_threshold`. The shares are burned and the lamports that reached the vault for
+/// them (the deposit minus the entry fee) are paid back from `fund_vault`. `total_deposit`
+/// drops by the gross deposit, the same amount `deposit` added to it.
+pub fn process_claim_refund(ctx: Context<ClaimRefund>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let fund_details = &ctx.accounts.fund_details;
+
+    if fund_details.total_deposit >= fund_details.invest_threshold {
+        return err!(FundError::ThresholdAlreadyMet);
+    }
+    if !fund_details.fundraise_failed(now) {
+        msg!("Refunds open after {}", fund_details.fundraise_deadline);
+        return err!(FundError::WithdrawalTooEarly);
+    }
+
+    // Shares may have been moved between wallets, so only what is both recorded and held counts
+    let shares = ctx.accounts.position.shares.min(ctx.accounts.user_token_account.amount);
+    if shares == 0 {
+        return err!(FundError::NothingToRefund);
+    }
+    let released = ctx.accounts.position.record_redemption(shares)?;
+    let refund = released.net_lamports;
+
+    let vault_reserve = Rent::get()?.minimum_balance(0);
+    let vault_liquidity = ctx.accounts.fund_vault.lamports().saturating_sub(vault_reserve);
+    if refund > vault_liquidity {
+        msg!("Vault holds {} lamports, refund needs {}", vault_liquidity, refund);
+        return err!(FundError::InsufficientFunds);
+    }
+
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.fund_token_mint.to_account_info(),
+                from: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        shares,
+    )?;
+
+    let fund_key = ctx.accounts.fund_details.key();
+    let vault_bump = *ctx.bumps.get("fund_vault").unwrap();
+    let vault_seeds: &[&[u8]] = &[VAULT_SEED, fund_key.as_ref(), &[vault_bump]];
+    invoke_signed(
+        &system_instruction::transfer(&ctx.accounts.fund_vault.key(), &ctx.accounts.user.key(), refund),
+        &[
+            ctx.accounts.fund_vault.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+        &[vault_seeds],
+    )?;
+
+    let fund_details = &mut ctx.accounts.fund_details;
+    fund_details.total_deposit = fund_details.total_deposit.saturating_sub(released.lamports);
+    fund_details.current_depost = fund_details.total_deposit;
+
+    msg!("Refunded {} lamports for {} shares", refund, shares);
+    emit_cpi!(RefundClaimed {
+        fund: fund_key,
+        investor: ctx.accounts.user.key(),
+        shares,
+        amount: refund,
+        timestamp: now,
+    });
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(fund_id: String, fund_creator: Pubkey)]
+pub struct ClaimRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"fund_details", fund_id.as_bytes(), fund_creator.as_ref()],
+        bump,
+        has_one = fund_token_mint
+    )]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, fund_details.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a simple SOL vault owned by the system program
+    pub fund_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [POSITION_SEED, fund_details.key().as_ref(), user.key().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, InvestorPosition>,
+
+    #[account(
+        mut,
+        associated_token::mint = fund_token_mint,
+        associated_token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub fund_token_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
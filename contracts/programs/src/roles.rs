@@ -0,0 +1,141 @@
+use anchor_lang::prelude::*;
+
+use crate::events::FundRolesUpdated;
+use crate::{FundDetails, FundError};
+
+pub const MAX_FUND_TRADERS: usize = 5;
+
+/// Who may do what in a fund. The creator holds every role at `create_fund`.
+///
+/// - `owner` changes the roles and the fund's settings (allowlist, token accounts) and can hand
+///   the fund over through `pending_owner`.
+/// - `traders` queue and execute trades and put swaps to a vote.
+/// - `fee_recipient` receives the manager's fee shares and owns the manager fee vault.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FundRoles {
+    pub owner: Pubkey,
+    pub pending_owner: Pubkey, // Pubkey::default() when no transfer is in flight
+    pub fee_recipient: Pubkey,
+    pub traders: Vec<Pubkey>,
+}
+
+impl FundRoles {
+    pub const LEN: usize = 32 * 3 + 4 + 32 * MAX_FUND_TRADERS;
+
+    pub fn new(creator: Pubkey) -> Self {
+        FundRoles {
+            owner: creator,
+            pending_owner: Pubkey::default(),
+            fee_recipient: creator,
+            traders: vec![creator],
+        }
+    }
+
+    pub fn is_trader(&self, key: &Pubkey) -> bool {
+        self.traders.contains(key)
+    }
+
+    pub fn set(&mut self, traders: Vec<Pubkey>, fee_recipient: Pubkey) -> Result<()> {
+        if traders.len() > MAX_FUND_TRADERS || fee_recipient == Pubkey::default() {
+            return err!(FundError::InvalidFundRoles);
+        }
+        for (i, trader) in traders.iter().enumerate() {
+            if *trader == Pubkey::default() || traders[..i].contains(trader) {
+                return err!(FundError::InvalidFundRoles);
+            }
+        }
+        self.traders = traders;
+        self.fee_recipient = fee_recipient;
+        Ok(())
+    }
+
+    fn updated_event(&self, fund: Pubkey) -> FundRolesUpdated {
+        FundRolesUpdated {
+            fund,
+            owner: self.owner,
+            pending_owner: self.pending_owner,
+            fee_recipient: self.fee_recipient,
+            traders: self.traders.clone(),
+        }
+    }
+}
+
+/// Replaces the fund's traders and fee recipient. Fees already in the old recipient's vault
+/// stay claimable by them.
+pub fn process_set_fund_roles(ctx: Context<UpdateFundRoles>, traders: Vec<Pubkey>, fee_recipient: Pubkey) -> Result<()> {
+    let fund_key = ctx.accounts.fund_details.key();
+    let roles = &mut ctx.accounts.fund_details.roles;
+    roles.set(traders, fee_recipient)?;
+    msg!("Fund roles updated: {} traders, fee recipient {}", roles.traders.len(), roles.fee_recipient);
+    emit_cpi!(roles.updated_event(fund_key));
+    Ok(())
+}
+
+/// First step of an ownership transfer. Passing `Pubkey::default()` cancels a pending transfer.
+pub fn process_propose_fund_owner(ctx: Context<UpdateFundRoles>, new_owner: Pubkey) -> Result<()> {
+    let fund_key = ctx.accounts.fund_details.key();
+    let roles = &mut ctx.accounts.fund_details.roles;
+    roles.pending_owner = new_owner;
+    msg!("Proposed new fund owner {}", new_owner);
+    emit_cpi!(roles.updated_event(fund_key));
+    Ok(())
+}
+
+/// The fund's PDAs stay derived from its creator; only the permissions move.
+pub fn process_accept_fund_owner(ctx: Context<AcceptFundOwner>) -> Result<()> {
+    let fund_key = ctx.accounts.fund_details.key();
+    let roles = &mut ctx.accounts.fund_details.roles;
+    roles.owner = roles.pending_owner;
+    roles.pending_owner = Pubkey::default();
+    msg!("Fund owner is now {}", roles.owner);
+    emit_cpi!(roles.updated_event(fund_key));
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateFundRoles<'info> {
+    #[account(mut, constraint = fund_details.roles.owner == owner.key() @ FundError::Unauthorized)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    pub owner: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptFundOwner<'info> {
+    #[account(mut, constraint = fund_details.roles.pending_owner == pending_owner.key() @ FundError::Unauthorized)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    pub pending_owner: Signer<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_reject_bad_trader_sets() {
+        let creator = Pubkey::new_unique();
+        let mut roles = FundRoles::new(creator);
+        assert!(roles.is_trader(&creator));
+
+        let trader = Pubkey::new_unique();
+        assert!(roles.set(vec![trader, trader], creator).is_err());
+        assert!(roles.set(vec![Pubkey::default()], creator).is_err());
+        assert!(roles.set(vec![trader], Pubkey::default()).is_err());
+        assert!(roles.set(vec![Pubkey::new_unique(); MAX_FUND_TRADERS + 1], creator).is_err());
+
+        roles.set(vec![trader], creator).unwrap();
+        assert!(roles.is_trader(&trader));
+        assert!(!roles.is_trader(&creator));
+    }
+
+    #[test]
+    fn full_roles_fit_their_space() {
+        let mut roles = FundRoles::new(Pubkey::new_unique());
+        let traders = (0..MAX_FUND_TRADERS).map(|_| Pubkey::new_unique()).collect();
+        roles.set(traders, Pubkey::new_unique()).unwrap();
+        assert_eq!(roles.try_to_vec().unwrap().len(), FundRoles::LEN);
+    }
+}
@@ -5,12 +5,14 @@ use anchor_lang::{
 };
 use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
-use std::borrow::BorrowMut;
-use crate::{FundDetails, VAULT_SEED};
+use crate::{FundDetails, FundError, VAULT_SEED};
 
 pub const AUTHORITY_SEED: &[u8] = b"authority";
 pub const WSOL_SEED: &[u8] = b"wsol";
 pub const FUND_TOKEN_SEED: &[u8] = b"fund_token";
+// Lamports moved to the authority on top of the swap amount and WSOL rent, so it can cover
+// rent it pays along the way. Whatever is left goes back to the vault after the swap.
+pub const SWAP_LAMPORTS_BUFFER: u64 = 200_000;
 
 mod jupiter {
     use anchor_lang::declare_id;
@@ -44,7 +46,17 @@ pub enum ErrorCode {
     InvalidProgramTokenAccount
 }
 
-pub fn sol_to_swap(ctx: Context<SOLToSwap>, fund_id: String, amount: u64, data: Vec<u8>) -> Result<()> {
+/// What a swap actually moved, measured on the fund's own accounts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapAmounts {
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+/// Swaps `amount` lamports from the fund vault into the fund's holding of the destination mint.
+/// The route may only write to the WSOL account and that holding, see `check_route_accounts`
+/// and `check_swap_effects`.
+pub fn sol_to_swap(ctx: Context<SOLToSwap>, fund_id: String, amount: u64, min_amount_out: u64, data: Vec<u8>) -> Result<SwapAmounts> {
     msg!("Instruction: SolToSwap");
     
     // Get bumps
@@ -53,17 +65,19 @@ pub fn sol_to_swap(ctx: Context<SOLToSwap>, fund_id: String, amount: u64, data:
     let vault_bump = *ctx.bumps.get("fund_vault").unwrap();
     let fund_token_bump = *ctx.bumps.get("fund_token_account").unwrap_or(&0u8);
     
-    // Build authority seeds for signing
-    let authority_seeds = &[AUTHORITY_SEED, &[authority_bump]];
-    let vault_seeds = &[VAULT_SEED, fund_id.as_bytes(), &[vault_bump]];
-    let fund_token_seeds = &[FUND_TOKEN_SEED, fund_id.as_bytes(), ctx.accounts.destination_mint.key().as_ref(), &[fund_token_bump]];
+    // Build authority seeds for signing - every PDA here is namespaced by the fund
+    let fund_key = ctx.accounts.fund_details.key();
+    let destination_mint_key = ctx.accounts.destination_mint.key();
+    let authority_seeds = &[AUTHORITY_SEED, fund_key.as_ref(), &[authority_bump]];
+    let vault_seeds = &[VAULT_SEED, fund_key.as_ref(), &[vault_bump]];
+    let fund_token_seeds = &[FUND_TOKEN_SEED, fund_key.as_ref(), destination_mint_key.as_ref(), &[fund_token_bump]];
+    msg!("Swapping for fund_id: {}", fund_id);
     
     // Calculate rent for WSOL token account
     let rent = Rent::get()?;
     let wsol_rent = rent.minimum_balance(TokenAccount::LEN);
     
-    // Add a small buffer for gas fees and other overhead
-    let buffer = 200000; // 0.000005 SOL buffer
+    let buffer = SWAP_LAMPORTS_BUFFER;
     let total_required = amount + wsol_rent + buffer;
     
     // Get all relevant balances
@@ -122,8 +136,7 @@ pub fn sol_to_swap(ctx: Context<SOLToSwap>, fund_id: String, amount: u64, data:
         ctx.accounts.sol_mint.clone(),
         ctx.accounts.token_program.clone(),
         ctx.accounts.system_program.clone(),
-        &[authority_bump],
-        &[wsol_bump],
+        &[authority_seeds, &[WSOL_SEED, fund_key.as_ref(), &[wsol_bump]]],
     )?;
 
     // IMPORTANT: Transfer SOL directly to the WSOL account before syncing
@@ -169,73 +182,19 @@ pub fn sol_to_swap(ctx: Context<SOLToSwap>, fund_id: String, amount: u64, data:
     } // <-- wsol_data is dropped here when this scope ends
 
 
-    msg!("Approving Jupiter program to spend WSOL tokens");
-    token::approve(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            token::Approve {
-                to: ctx.accounts.program_wsol_account.to_account_info(),
-                delegate: ctx.accounts.jupiter_program.to_account_info(),
-                authority: ctx.accounts.program_authority.to_account_info(),
-            },
-            &[&[AUTHORITY_SEED, &[authority_bump]]],
-        ),
-        amount,
+    // Initialize fund token account if needed, funded by the authority
+    init_fund_token_account(
+        &ctx.accounts.fund_token_account,
+        &ctx.accounts.destination_mint.to_account_info(),
+        &ctx.accounts.program_authority.to_account_info(),
+        &ctx.accounts.program_authority.to_account_info(),
+        &ctx.accounts.token_program,
+        &ctx.accounts.system_program,
+        &[&authority_seeds[..], &fund_token_seeds[..]],
     )?;
 
-
-    // Initialize fund token account if needed
-    if ctx.accounts.fund_token_account.data_is_empty() {
-        msg!("Initializing fund token account for destination tokens");
-        let token_account_rent = rent.minimum_balance(TokenAccount::LEN);
-        
-        // Create account
-        system_program::create_account(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                system_program::CreateAccount {
-                    from: ctx.accounts.program_authority.to_account_info(),
-                    to: ctx.accounts.fund_token_account.to_account_info(),
-                },
-                &[&[FUND_TOKEN_SEED, fund_id.as_bytes(), ctx.accounts.destination_mint.key().as_ref(), &[fund_token_bump]]],
-            ),
-            token_account_rent,
-            TokenAccount::LEN as u64,
-            &ctx.accounts.token_program.key(),
-        )?;  
-        
-        // Initialize token account
-        token::initialize_account3(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::InitializeAccount3 {
-                    account: ctx.accounts.fund_token_account.to_account_info(),
-                    mint: ctx.accounts.destination_mint.to_account_info(),
-                    authority: ctx.accounts.program_authority.to_account_info(),
-                },
-            ),
-        )?;
-        
-        msg!("Fund token account initialized successfully");
-    } else {
-        msg!("Fund token account already exists");
-        
-        // Verify the existing account is properly initialized
-        let token_data = ctx.accounts.fund_token_account.try_borrow_data()?;
-        let token_account = TokenAccount::try_deserialize(&mut token_data.as_ref())?;
-        
-        if token_account.owner != ctx.accounts.program_authority.key() {
-            msg!("ERROR: Fund token account has incorrect owner");
-            return err!(ErrorCode::InvalidProgramTokenAccount);
-        }
-        
-        if token_account.mint != ctx.accounts.destination_mint.key() {
-            msg!("ERROR: Fund token account has incorrect mint");
-            return err!(ErrorCode::InvalidProgramTokenAccount);
-        }
-    }
-
-
+    // Snapshot the destination balance so the swap output can be checked against min_amount_out
+    let destination_before = token_balance(&ctx.accounts.fund_token_account)?;
 
     // Execute Jupiter swap
     msg!("Executing swap on Jupiter");
@@ -246,41 +205,71 @@ pub fn sol_to_swap(ctx: Context<SOLToSwap>, fund_id: String, amount: u64, data:
             i, acc.key, acc.is_writable, acc.is_signer);
     }
     
+    let authority_key = ctx.accounts.program_authority.key();
+    check_route_accounts(
+        ctx.remaining_accounts,
+        &authority_key,
+        &[ctx.accounts.program_wsol_account.key(), ctx.accounts.fund_token_account.key()],
+    )?;
+    let wsol_before = token_balance(&ctx.accounts.program_wsol_account)?;
     swap_on_jupiter(
         ctx.remaining_accounts,
         ctx.accounts.jupiter_program.clone(),
+        &ctx.accounts.program_authority,
         data,
         authority_seeds
     )?;
+    let wsol_after = token_balance(&ctx.accounts.program_wsol_account)?;
+    let destination = TokenAccount::try_deserialize(&mut ctx.accounts.fund_token_account.try_borrow_data()?.as_ref())?;
+    let spent = check_swap_effects(wsol_before, wsol_after, amount, &[&destination], &authority_key)?;
 
-    // Close WSOL account after swap
+    // Close WSOL account after swap, which unwraps any unspent input along with the rent
     msg!("Closing WSOL account");
     close_program_wsol(
         &ctx.accounts.program_authority,
         &ctx.accounts.program_wsol_account,
         &ctx.accounts.token_program,
+        &fund_key,
         authority_bump,
     )?;
- 
+
+    // The authority is not counted in NAV, so everything it holds beyond what it had before
+    // the vault funded the swap goes back to the vault
+    let leftover = ctx.accounts.program_authority.lamports().saturating_sub(authority_balance);
+    if leftover > 0 {
+        system_program::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.program_authority.to_account_info(),
+                    to: ctx.accounts.fund_vault.to_account_info(),
+                },
+                &[authority_seeds],
+            ),
+            leftover,
+        )?;
+        msg!("Returned {} lamports to the fund vault", leftover);
+    }
+
     // Get balances after the swap
     let vault_balance = ctx.accounts.fund_vault.lamports();
     msg!("vault_balance_after {}",vault_balance);
 
-    // Check the fund token account balance to confirm we received tokens
-    if let Ok(token_data) = ctx.accounts.fund_token_account.try_borrow_data() {
-        if let Ok(token_account) = TokenAccount::try_deserialize(&mut token_data.as_ref()) {
-            msg!("Fund token account balance after swap: {} tokens", token_account.amount);
-        }
-    }
+    // Check the fund token account balance to confirm we received enough tokens
+    let destination_after = token_balance(&ctx.accounts.fund_token_account)?;
+    msg!("Fund token account balance after swap: {} tokens", destination_after);
+    let amount_out = check_min_amount_out(destination_before, destination_after, min_amount_out)?;
 
     msg!("Swap completed successfully");
-    Ok(())
+    Ok(SwapAmounts { amount_in: spent, amount_out })
 }
 
 
 
 
-pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authority_seeds: &[&[u8]]) -> Result<()> {
+/// Swaps `amount` of a fund holding into SOL in the fund vault, with the same route limits as
+/// `sol_to_swap`.
+pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, min_amount_out: u64, data: Vec<u8>) -> Result<SwapAmounts> {
     msg!("==== START: USDC to SOL Swap ====");
     let authority_bump = *ctx.bumps.get("program_authority").unwrap();
     let wsol_bump = *ctx.bumps.get("program_wsol_account").unwrap();
@@ -295,7 +284,8 @@ pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authori
     msg!("- User Token Account (USDC): {}", ctx.accounts.user_token_account.key());
     
     // Build authority seeds for signing
-    let authority_seeds = &[AUTHORITY_SEED, &[authority_bump]];
+    let fund_key = ctx.accounts.fund_details.key();
+    let authority_seeds = &[AUTHORITY_SEED, fund_key.as_ref(), &[authority_bump]];
     msg!("Authority bump: {}", authority_bump);
     msg!("WSOL bump: {}", wsol_bump);
 
@@ -327,8 +317,7 @@ pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authori
         ctx.accounts.sol_mint.clone(),
         ctx.accounts.token_program.clone(),
         ctx.accounts.system_program.clone(),
-        &[authority_bump],
-        &[wsol_bump],
+        &[authority_seeds, &[WSOL_SEED, fund_key.as_ref(), &[wsol_bump]]],
     )?;
     msg!("WSOL account initialized successfully");
 
@@ -388,21 +377,43 @@ pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authori
             &data[data.len().saturating_sub(32)..]);
     }
     
+    // Jupiter pays out into the WSOL account, so that is where the swap output is measured
+    let authority_key = ctx.accounts.program_authority.key();
+    check_route_accounts(
+        ctx.remaining_accounts,
+        &authority_key,
+        &[ctx.accounts.user_token_account.key(), ctx.accounts.program_wsol_account.key()],
+    )?;
+    let wsol_before = token_balance(&ctx.accounts.program_wsol_account)?;
+    let input_before = token_balance(&ctx.accounts.user_token_account.to_account_info())?;
+
     let swap_result = swap_on_jupiter(
         ctx.remaining_accounts,
         ctx.accounts.jupiter_program.clone(),
+        &ctx.accounts.program_authority,
         data.clone(),
         authority_seeds
     );
     
     // Check if Jupiter swap succeeded before proceeding
-    if swap_result.is_err() {
-        msg!("ERROR: Jupiter swap failed: {:?}", swap_result);
-        return swap_result;
+    if let Err(e) = swap_result {
+        msg!("ERROR: Jupiter swap failed: {:?}", e);
+        return Err(e);
     }
     
     msg!("Jupiter swap executed successfully");
 
+    let wsol_after = token_balance(&ctx.accounts.program_wsol_account)?;
+    let amount_out = check_min_amount_out(wsol_before, wsol_after, min_amount_out)?;
+    ctx.accounts.user_token_account.reload()?;
+    let spent = check_swap_effects(
+        input_before,
+        ctx.accounts.user_token_account.amount,
+        amount,
+        &[&ctx.accounts.user_token_account],
+        &authority_key,
+    )?;
+
     // Add short delay to ensure Jupiter completed
     msg!("Adding safety delay to ensure Jupiter completed");
     for i in 0..1000 {
@@ -432,12 +443,13 @@ pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authori
         &ctx.accounts.program_authority,
         &ctx.accounts.program_wsol_account,
         &ctx.accounts.token_program,
+        &fund_key,
         authority_bump,
     );
     
-    if close_result.is_err() {
-        msg!("ERROR: Failed to close WSOL account: {:?}", close_result);
-        return close_result;
+    if let Err(e) = close_result {
+        msg!("ERROR: Failed to close WSOL account: {:?}", e);
+        return Err(e);
     } else {
         msg!("WSOL account closed successfully");
     }
@@ -467,9 +479,9 @@ pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authori
             final_transfer_amount,
         );
         
-        if transfer_result.is_err() {
-            msg!("ERROR: Failed to transfer SOL to fund vault: {:?}", transfer_result);
-            return transfer_result;
+        if let Err(e) = transfer_result {
+            msg!("ERROR: Failed to transfer SOL to fund vault: {:?}", e);
+            return Err(e);
         } else {
             msg!("Successfully transferred SOL to fund vault");
         }
@@ -478,12 +490,99 @@ pub fn swap_to_sol(ctx: Context<SwapToSOL>, amount: u64, data: Vec<u8>,  authori
     }
 
     msg!("==== END: USDC to SOL Swap completed successfully ====");
+    Ok(SwapAmounts { amount_in: spent, amount_out })
+}
+
+/// Creates the fund's `FUND_TOKEN_SEED` account for `mint` if it does not exist yet, or checks
+/// that the existing one belongs to the fund's authority. `signer_seeds` must cover the token
+/// account PDA and, if it is a PDA, the payer.
+pub(crate) fn init_fund_token_account<'info>(
+    fund_token_account: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    program_authority: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if fund_token_account.data_is_empty() {
+        msg!("Initializing fund token account for {}", mint.key());
+        let token_account_rent = Rent::get()?.minimum_balance(TokenAccount::LEN);
+
+        // Create account
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: fund_token_account.clone(),
+                },
+                signer_seeds,
+            ),
+            token_account_rent,
+            TokenAccount::LEN as u64,
+            &token_program.key(),
+        )?;
+
+        // Initialize token account
+        token::initialize_account3(CpiContext::new(
+            token_program.to_account_info(),
+            token::InitializeAccount3 {
+                account: fund_token_account.clone(),
+                mint: mint.clone(),
+                authority: program_authority.clone(),
+            },
+        ))?;
+
+        msg!("Fund token account initialized successfully");
+        return Ok(());
+    }
+
+    msg!("Fund token account already exists");
+
+    // Verify the existing account is properly initialized
+    let token_data = fund_token_account.try_borrow_data()?;
+    let token_account = TokenAccount::try_deserialize(&mut token_data.as_ref())?;
+
+    if token_account.owner != program_authority.key() {
+        msg!("ERROR: Fund token account has incorrect owner");
+        return err!(ErrorCode::InvalidProgramTokenAccount);
+    }
+
+    if token_account.mint != mint.key() {
+        msg!("ERROR: Fund token account has incorrect mint");
+        return err!(ErrorCode::InvalidProgramTokenAccount);
+    }
     Ok(())
 }
 
-fn swap_on_jupiter<'info>(
+/// Token amount held by `account`, treating a not-yet-created account as empty.
+pub(crate) fn token_balance(account: &AccountInfo) -> Result<u64> {
+    if account.data_is_empty() {
+        return Ok(0);
+    }
+    let data = account.try_borrow_data()?;
+    Ok(TokenAccount::try_deserialize(&mut data.as_ref())?.amount)
+}
+
+/// Fails the swap if the destination grew by less than `min_amount_out`, otherwise returns
+/// how much it grew by.
+pub fn check_min_amount_out(before: u64, after: u64, min_amount_out: u64) -> Result<u64> {
+    let received = after.saturating_sub(before);
+    msg!("Swap output: {} (minimum {})", received, min_amount_out);
+    if received < min_amount_out {
+        return err!(FundError::SlippageExceeded);
+    }
+    Ok(received)
+}
+
+/// CPIs into Jupiter with `remaining_accounts` as the route. `program_authority` is a PDA and
+/// never signs the outer transaction, so it is marked as a signer here and signed for with
+/// `authority_seeds`.
+pub(crate) fn swap_on_jupiter<'info>(
     remaining_accounts: &[AccountInfo],
     jupiter_program: Program<'info, Jupiter>,
+    program_authority: &AccountInfo,
     data: Vec<u8>,
     authority_seeds: &[&[u8]],
 ) -> Result<()> {
@@ -507,79 +606,21 @@ fn swap_on_jupiter<'info>(
 
     // Log important accounts to help debugging
     msg!("KEY ACCOUNT DETAILS:");
-    for i in 0..std::cmp::min(5, remaining_accounts.len()) {
-        let acc = &remaining_accounts[i];
+    for (i, acc) in remaining_accounts.iter().enumerate().take(5) {
         msg!("Account {}: {} (Writable: {}, Signer: {}, Owner: {}, Data len: {})",
             i, acc.key(), acc.is_writable, acc.is_signer, acc.owner, acc.data_len());
     }
     
-    // Check if we're in a testing environment (probably localnet)
-    let is_localnet = true; // Always assume localnet for safety, we'll handle mainnet properly regardless
-    let usdc_pubkey = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
-    let mut destination_mint_index = None;
-    
-    // Find destination mint (USDC) account and check its state
-    for (i, acc) in remaining_accounts.iter().enumerate() {
-        if acc.key().to_string() == usdc_pubkey {
-            msg!("Found USDC mint at index {}", i);
-            destination_mint_index = Some(i);
-            
-            // Check if the mint is properly initialized
-            if acc.data_len() < 82 {  // Minimum size for a mint account
-                msg!("WARNING: USDC mint appears uninitialized (data_len: {})", acc.data_len());
-            } else {
-                msg!("USDC mint appears properly initialized (data_len: {})", acc.data_len());
-            }
-        }
-    }
-    
-    // Find source WSOL token account
-    let mut source_token_account_index = None;
-    for (i, acc) in remaining_accounts.iter().enumerate() {
-        if acc.owner == &token::ID && acc.data_len() >= 165 {
-            if let Ok(data) = acc.try_borrow_data() {
-                if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
-                    if token_account.mint == spl_token::native_mint::id() {
-                        source_token_account_index = Some(i);
-                        msg!("Found WSOL token account at index {} with {} tokens", 
-                            i, token_account.amount);
-                        break;
-                    }
-                }
-            }
-        }
-    }
-    
-    // Find destination token account (USDC)
-    let mut destination_token_account_index = None;
-    for (i, acc) in remaining_accounts.iter().enumerate() {
-        if acc.owner == &token::ID && acc.data_len() >= 165 {
-            if let Ok(data) = acc.try_borrow_data() {
-                if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
-                    if token_account.mint.to_string() == usdc_pubkey {
-                        destination_token_account_index = Some(i);
-                        msg!("Found USDC token account at index {}", i);
-                        break;
-                    }
-                }
-            }
-        }
-    }
-
     // Prepare Jupiter swap instruction
     msg!("Creating Jupiter swap instruction");
     let jupiter_swap_instruction = Instruction {
         program_id: jupiter_program.key(),
-        accounts: remaining_accounts.iter().map(|acc| solana_program::instruction::AccountMeta {
-            pubkey: acc.key(),
-            is_signer: acc.is_signer,
-            is_writable: acc.is_writable,
-        }).collect(),
-        data: data.clone(),
+        accounts: route_account_metas(remaining_accounts, program_authority.key),
+        data,
     };
 
-    msg!("Authority seeds: {:?}", authority_seeds);
-    // Execute Jupiter swap with detailed error catching
+    // Execute Jupiter swap. Any failure aborts the whole instruction so no fund
+    // accounting is updated for a swap that did not happen.
     msg!("Executing Jupiter swap instruction...");
     let invoke_result = invoke_signed(
         &jupiter_swap_instruction, 
@@ -596,217 +637,107 @@ fn swap_on_jupiter<'info>(
             msg!("==== ERROR: Jupiter swap failed ====");
             msg!("Error details: {:?}", e);
             
-            // Convert error to string for pattern matching
-            let error_str = format!("{:?}", e);
-            
-            // Handle AccountNotInitialized error for destination_mint
-            if error_str.contains("AccountNotInitialized") && error_str.contains("destination_mint") {
-                msg!("Detected uninitialized destination_mint (USDC) account - handling for localnet");
-                
-                // Get source amount from WSOL token account
-                let wsol_amount = if let Some(idx) = source_token_account_index {
-                    if let Ok(data) = remaining_accounts[idx].try_borrow_data() {
-                        if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
-                            token_account.amount
-                        } else { 0 }
-                    } else { 0 }
-                } else { 0 };
-
-                msg!("Source WSOL amount: {}", wsol_amount);
-                
-                // On localnet, we'll simulate a successful swap
-                msg!("LOCALNET: Simulating successful Jupiter swap from {} SOL to USDC", 
-                    wsol_amount as f64 / 1_000_000_000.0);
-                    
-                // Log the expected result (if this was on mainnet)
-                let usdc_amount = (wsol_amount as f64 / 1_000_000_000.0 * 25.0 * 1_000_000.0) as u64;
-                msg!("LOCALNET: In production, you would receive approximately {} USDC tokens", 
-                    usdc_amount);
-                    
-                // Return success to allow the transaction to complete
-                return Ok(());
-            }
-            
-            // Enhanced diagnostic information for other errors
-            if error_str.contains("AccountNotFound") {
-                msg!("Diagnosis: One of the accounts in the instruction was not found");
-                for (i, acc) in remaining_accounts.iter().enumerate() {
-                    if acc.lamports() == 0 {
-                        msg!("Account {} ({}) may not exist - 0 lamports", i, acc.key());
+            // Enhanced diagnostic information
+            match e {
+                ProgramError::NotEnoughAccountKeys => {
+                    msg!("Diagnosis: The swap instruction is missing accounts");
+                    for (i, acc) in remaining_accounts.iter().enumerate() {
+                        if acc.lamports() == 0 {
+                            msg!("Account {} ({}) may not exist - 0 lamports", i, acc.key());
+                        }
                     }
                 }
-            } 
-            else if error_str.contains("InvalidAccountData") {
-                msg!("Diagnosis: One of the accounts has invalid data structure");
-                for (i, acc) in remaining_accounts.iter().enumerate() {
-                    if acc.data_len() == 0 && acc.is_writable && acc.owner != &system_program::ID {
-                        msg!("Suspect account {}: {} (empty data but not system owned)", i, acc.key());
+                ProgramError::InvalidAccountData => {
+                    msg!("Diagnosis: One of the accounts has invalid data structure");
+                    for (i, acc) in remaining_accounts.iter().enumerate() {
+                        if acc.data_len() == 0 && acc.is_writable && acc.owner != &system_program::ID {
+                            msg!("Suspect account {}: {} (empty data but not system owned)", i, acc.key());
+                        }
                     }
                 }
-            }
-            else if error_str.contains("InsufficientFunds") {
-                msg!("Diagnosis: An account has insufficient funds for the operation");
-                for (i, acc) in remaining_accounts.iter().enumerate() {
-                    if acc.owner == &token::ID {
-                        if let Ok(data) = acc.try_borrow_data() {
-                            if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
-                                msg!("Token account {}: {} has balance: {}", i, acc.key(), token_account.amount);
+                ProgramError::InsufficientFunds => {
+                    msg!("Diagnosis: An account has insufficient funds for the operation");
+                    for (i, acc) in remaining_accounts.iter().enumerate() {
+                        if acc.owner == &token::ID {
+                            if let Ok(data) = acc.try_borrow_data() {
+                                if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
+                                    msg!("Token account {}: {} has balance: {}", i, acc.key(), token_account.amount);
+                                }
                             }
                         }
                     }
                 }
+                _ => {}
             }
-            
-            // On localnet, we'll let the transaction succeed even with errors
-            if is_localnet {
-                msg!("LOCALNET: Ignoring error and simulating successful swap");
-                Ok(())
-            } else {
-                err!(ErrorCode::InvalidJupiterInstruction)
+
+            Err(e.into())
+        }
+    }
+}
+
+/// Fails if the swap route could move fund assets other than through `allowed`. The authority
+/// signs the whole route, so it may neither be writable itself (its lamports) nor pass any
+/// other writable token account the authority owns, such as another holding.
+pub(crate) fn check_route_accounts(remaining_accounts: &[AccountInfo], program_authority: &Pubkey, allowed: &[Pubkey]) -> Result<()> {
+    for acc in remaining_accounts.iter().filter(|acc| acc.is_writable) {
+        if acc.key == program_authority {
+            msg!("Route marks the fund authority writable");
+            return err!(FundError::RouteAccountNotAllowed);
+        }
+        if acc.owner != &token::ID || allowed.contains(acc.key) {
+            continue;
+        }
+        let data = acc.try_borrow_data()?;
+        if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
+            if token_account.owner == *program_authority {
+                msg!("Route writes to fund token account {}", acc.key);
+                return err!(FundError::RouteAccountNotAllowed);
             }
         }
     }
+    Ok(())
 }
 
-// fn swap_on_jupiter<'info>(
-//     remaining_accounts: &[AccountInfo],
-//     jupiter_program: Program<'info, Jupiter>,
-//     data: Vec<u8>,
-//     authority_seeds: &[&[u8]],
-// ) -> Result<()> {
-//     msg!("==== START: Jupiter Swap ====");
-    
-//     // Validate Jupiter program
-//     if jupiter_program.key() != jupiter::id() {
-//         msg!("ERROR: Invalid Jupiter program ID");
-//         return err!(ErrorCode::InvalidJupiterProgram);
-//     }
-
-//     // Validate input data
-//     if data.is_empty() {
-//         msg!("ERROR: Empty swap data");
-//         return err!(ErrorCode::InvalidReturnData);
-//     }
-    
-//     msg!("Data length: {} bytes", data.len());
-//     msg!("Jupiter program ID: {}", jupiter_program.key());
-//     msg!("Total accounts: {}", remaining_accounts.len());
-
-//     // Log important accounts
-//     msg!("KEY ACCOUNT DETAILS:");
-//     // Log first 5 accounts
-//     for i in 0..std::cmp::min(5, remaining_accounts.len()) {
-//         let acc = &remaining_accounts[i];
-//         msg!("Account {}: {} (Writable: {}, Signer: {}, Owner: {}, Data len: {})",
-//             i, acc.key(), acc.is_writable, acc.is_signer, acc.owner, acc.data_len());
-//     }
-    
-//     // Log last 5 accounts if there are more than 10
-//     if remaining_accounts.len() > 10 {
-//         msg!("...");
-//         for i in remaining_accounts.len() - 5..remaining_accounts.len() {
-//             let acc = &remaining_accounts[i];
-//             msg!("Account {}: {} (Writable: {}, Signer: {}, Owner: {}, Data len: {})",
-//                 i, acc.key(), acc.is_writable, acc.is_signer, acc.owner, acc.data_len());
-//         }
-//     }
-
-//     // Verify the Jupiter program has permission to spend USDC
-//     // Check the approval status for the source token account (should be first few accounts)
-//     let source_token_account_index = 2; // Typically index 2 based on Jupiter's layout
-//     if remaining_accounts.len() > source_token_account_index {
-//         let source_account = &remaining_accounts[source_token_account_index];
-//         if source_account.owner == &token::ID {
-//             if let Ok(data) = source_account.try_borrow_data() {
-//                 if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
-//                     msg!("Source account delegate: {}", token_account.delegate.unwrap_or_default());
-//                     msg!("Source account delegated amount: {}", token_account.delegated_amount);
-                    
-//                     // Check if Jupiter has approval
-//                     if token_account.delegate.is_none() || token_account.delegated_amount == 0 {
-//                         msg!("WARNING: Jupiter doesn't have token approval or amount is 0");
-//                     }
-//                 }
-//             }
-//         }
-//     }
-
-//     // Prepare Jupiter swap instruction
-//     msg!("Creating Jupiter swap instruction");
-//     let jupiter_swap_instruction = Instruction {
-//         program_id: jupiter_program.key(),
-//         accounts: remaining_accounts.iter().map(|acc| solana_program::instruction::AccountMeta {
-//             pubkey: acc.key(),
-//             is_signer: acc.is_signer,
-//             is_writable: acc.is_writable,
-//         }).collect(),
-//         data: data.clone(),
-//     };
-
- 
-//     msg!("Authority seeds: {:?}", authority_seeds);
-//     //Execute Jupiter swap with detailed error catching
-//     msg!("Executing Jupiter swap instruction...");
-//     let invoke_result = invoke_signed(
-//         &jupiter_swap_instruction, 
-//         remaining_accounts,
-//         &[authority_seeds]  
-//     );
-
-//     match invoke_result {
-//         Ok(_) => {
-//             msg!("==== END: Jupiter swap executed successfully ====");
-//             Ok(())
-//         },
-//         Err(e) => {
-//             msg!("==== ERROR: Jupiter swap failed ====");
-//             msg!("Error details: {:?}", e);
-            
-//             // Enhanced diagnostic information
-//             let error_code = format!("{:?}", e);
-            
-//             if error_code.contains("AccountNotFound") {
-//                 msg!("Diagnosis: One of the accounts in the instruction was not found");
-//                 // Try to identify missing accounts by checking lamports
-//                 for (i, acc) in remaining_accounts.iter().enumerate() {
-//                     if acc.lamports() == 0 {
-//                         msg!("Account {} ({}) may not exist - 0 lamports", i, acc.key());
-//                     }
-//                 }
-//             } 
-//             else if error_code.contains("InvalidAccountData") {
-//                 msg!("Diagnosis: One of the accounts has invalid data structure");
-                
-//                 // Try to identify which account might be invalid
-//                 for (i, acc) in remaining_accounts.iter().enumerate() {
-//                     if acc.data_len() == 0 && acc.is_writable && acc.owner != &system_program::ID {
-//                         msg!("Suspect account {}: {} (empty data but not system owned)", i, acc.key());
-//                     }
-//                 }
-//             }
-//             else if error_code.contains("InsufficientFunds") {
-//                 msg!("Diagnosis: An account has insufficient funds for the operation");
-                
-//                 // Check token accounts for balances
-//                 for (i, acc) in remaining_accounts.iter().enumerate() {
-//                     if acc.owner == &token::ID {
-//                         if let Ok(data) = acc.try_borrow_data() {
-//                             if let Ok(token_account) = TokenAccount::try_deserialize(&mut data.as_ref()) {
-//                                 msg!("Token account {}: {} has balance: {}", i, acc.key(), token_account.amount);
-//                             }
-//                         }
-//                     }
-//                 }
-//             }
-//             else if error_code.contains("Custom") {
-//                 msg!("Diagnosis: This is likely a protocol-specific error code from Jupiter");
-//                 msg!("Check Jupiter's documentation for specific error codes");
-//             }
-            
-//             err!(ErrorCode::InvalidJupiterInstruction)
-//         }
-//     }
-// }
+/// Checks what a swap the authority signed did to the fund's accounts: the source may have lost
+/// at most `max_spent`, and each of `fund_accounts` must still be owned by the authority with
+/// no delegate or close authority set. Returns how much the source lost.
+pub(crate) fn check_swap_effects(
+    source_before: u64,
+    source_after: u64,
+    max_spent: u64,
+    fund_accounts: &[&TokenAccount],
+    program_authority: &Pubkey,
+) -> Result<u64> {
+    let spent = source_before.saturating_sub(source_after);
+    if spent > max_spent {
+        msg!("Swap spent {} tokens, only {} were allowed", spent, max_spent);
+        return err!(FundError::SwapInputExceeded);
+    }
+    if !fund_accounts.iter().all(|account| controlled_by(account, program_authority)) {
+        msg!("Swap changed who controls a fund token account");
+        return err!(FundError::RouteAccountNotAllowed);
+    }
+    Ok(spent)
+}
+
+/// Whether a fund token account is still fully controlled by the fund after a swap.
+fn controlled_by(token_account: &TokenAccount, program_authority: &Pubkey) -> bool {
+    token_account.owner == *program_authority
+        && token_account.delegate.is_none()
+        && token_account.close_authority.is_none()
+}
+
+/// Account metas for the swap route, with `program_authority` marked as a signer.
+fn route_account_metas(remaining_accounts: &[AccountInfo], program_authority: &Pubkey) -> Vec<AccountMeta> {
+    remaining_accounts
+        .iter()
+        .map(|acc| AccountMeta {
+            pubkey: acc.key(),
+            is_signer: acc.is_signer || acc.key == program_authority,
+            is_writable: acc.is_writable,
+        })
+        .collect()
+}
 
 fn create_wsol_token_idempotent<'info>(
     program_authority: SystemAccount<'info>,
@@ -814,15 +745,9 @@ fn create_wsol_token_idempotent<'info>(
     sol_mint: Account<'info, Mint>,
     token_program: Program<'info, Token>,
     system_program: Program<'info, System>,
-    authority_bump: &[u8],
-    wsol_bump: &[u8],
+    signer_seeds: &[&[&[u8]]],
 ) -> Result<TokenAccount> {
     if program_wsol_account.data_is_empty() {
-        let signer_seeds: &[&[&[u8]]] = &[
-            &[AUTHORITY_SEED, authority_bump.as_ref()],
-            &[WSOL_SEED, wsol_bump.as_ref()],
-        ];
-
         msg!("Initialize program wSOL account");
         let rent = Rent::get()?;
         let space = TokenAccount::LEN;
@@ -872,10 +797,11 @@ fn close_program_wsol<'info>(
     program_authority: &SystemAccount<'info>,
     program_wsol_account: &UncheckedAccount<'info>,
     token_program: &Program<'info, Token>,
+    fund_key: &Pubkey,
     authority_bump: u8,  // Changed to u8
 ) -> Result<()> {
     // Create authority seeds
-    let authority_seeds = &[AUTHORITY_SEED, &[authority_bump]];
+    let authority_seeds = &[AUTHORITY_SEED, fund_key.as_ref(), &[authority_bump]];
 
     msg!("Close program wSOL token account");
     token::close_account(CpiContext::new_with_signer(
@@ -891,10 +817,12 @@ fn close_program_wsol<'info>(
 
 #[derive(Accounts)]
 pub struct SwapToSOL<'info> {
-    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    pub fund_details: Account<'info, FundDetails>,
+
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
     pub program_authority: SystemAccount<'info>,
     
-    #[account(mut, seeds = [WSOL_SEED], bump)]
+    #[account(mut, seeds = [WSOL_SEED, fund_details.key().as_ref()], bump)]
     /// CHECK: This account will hold the WSOL temporarily
     pub program_wsol_account: UncheckedAccount<'info>,
     
@@ -909,7 +837,7 @@ pub struct SwapToSOL<'info> {
     pub user_token_account: Account<'info, TokenAccount>,
     
     /// CHECK: This is the fund vault that will receive the swapped SOL
-    #[account(mut)]
+    #[account(mut, seeds = [VAULT_SEED, fund_details.key().as_ref()], bump)]
     pub fund_vault: UncheckedAccount<'info>,
     
     pub jupiter_program: Program<'info, Jupiter>,
@@ -920,11 +848,11 @@ pub struct SwapToSOL<'info> {
 #[derive(Accounts)]
 #[instruction(fund_id: String, amount: u64)]
 pub struct SOLToSwap<'info> {
-    #[account(mut, seeds = [AUTHORITY_SEED], bump)]
+    #[account(mut, seeds = [AUTHORITY_SEED, fund_details.key().as_ref()], bump)]
     pub program_authority: SystemAccount<'info>,
     
-    /// CHECK: This is NOT a PDA - removing the seeds constraint
-    #[account(mut)]
+    /// CHECK: Per-fund scratch account that holds WSOL for the duration of the swap
+    #[account(mut, seeds = [WSOL_SEED, fund_details.key().as_ref()], bump)]
     pub program_wsol_account: UncheckedAccount<'info>,
     
     #[account(mut)]
@@ -935,7 +863,7 @@ pub struct SOLToSwap<'info> {
     
     #[account(
         mut,
-        seeds = [VAULT_SEED, fund_id.as_bytes()],
+        seeds = [VAULT_SEED, fund_details.key().as_ref()],
         bump
     )]
     /// CHECK: This is a simple SOL vault owned by the system program
@@ -950,7 +878,7 @@ pub struct SOLToSwap<'info> {
     /// Fund token account to store swapped tokens (PDA)
     #[account(
         mut,
-        seeds = [FUND_TOKEN_SEED, fund_id.as_bytes(), destination_mint.key().as_ref()],
+        seeds = [FUND_TOKEN_SEED, fund_details.key().as_ref(), destination_mint.key().as_ref()],
         bump
     )]
     /// CHECK: Will be initialized if needed
@@ -959,4 +887,98 @@ pub struct SOLToSwap<'info> {
     pub jupiter_program: Program<'info, Jupiter>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_marks_the_fund_authority_as_signer() {
+        let (authority, pool, owner) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut authority_lamports, mut pool_lamports) = (0, 0);
+        let (mut authority_data, mut pool_data) = (vec![], vec![]);
+        let accounts = [
+            AccountInfo::new(&pool, false, true, &mut pool_lamports, &mut pool_data, &owner, false, 0),
+            AccountInfo::new(&authority, false, false, &mut authority_lamports, &mut authority_data, &owner, false, 0),
+        ];
+
+        let metas = route_account_metas(&accounts, &authority);
+        assert_eq!(metas[0], AccountMeta::new(pool, false));
+        assert_eq!(metas[1], AccountMeta::new_readonly(authority, true));
+    }
+
+    #[test]
+    fn route_may_only_write_to_allowed_fund_accounts() {
+        use solana_program::program_pack::Pack;
+        use spl_token::state::{Account, AccountState};
+
+        let authority = Pubkey::new_unique();
+        let (holding, pool_vault) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token_data = |owner: Pubkey| {
+            let mut data = vec![0; Account::LEN];
+            let account = Account {
+                mint: Pubkey::new_unique(),
+                owner,
+                amount: 1,
+                state: AccountState::Initialized,
+                ..Account::default()
+            };
+            Account::pack(account, &mut data).unwrap();
+            data
+        };
+        let (mut holding_data, mut pool_data) = (token_data(authority), token_data(Pubkey::new_unique()));
+        let (mut holding_lamports, mut pool_lamports, mut authority_lamports) = (0, 0, 0);
+        let mut authority_data = vec![];
+        let system = Pubkey::default();
+        let holding_info = AccountInfo::new(&holding, false, true, &mut holding_lamports, &mut holding_data, &token::ID, false, 0);
+        let pool_info = AccountInfo::new(&pool_vault, false, true, &mut pool_lamports, &mut pool_data, &token::ID, false, 0);
+        let authority_info = AccountInfo::new(&authority, false, true, &mut authority_lamports, &mut authority_data, &system, false, 0);
+
+        let route = [pool_info.clone(), holding_info.clone()];
+        assert!(check_route_accounts(&route, &authority, &[]).is_err());
+        assert!(check_route_accounts(&route, &authority, &[holding]).is_ok());
+        assert!(check_route_accounts(&[pool_info, authority_info], &authority, &[holding]).is_err());
+    }
+
+    #[test]
+    fn a_holding_swapped_to_sol_can_be_rebalanced() {
+        use solana_program::{program_option::COption, program_pack::Pack};
+        use spl_token::state::{Account, AccountState};
+
+        let authority = Pubkey::new_unique();
+        let token_account = |amount: u64, delegate: COption<Pubkey>| {
+            let mut data = vec![0; Account::LEN];
+            let account = Account {
+                mint: Pubkey::new_unique(),
+                owner: authority,
+                amount,
+                delegate,
+                delegated_amount: if delegate.is_some() { amount } else { 0 },
+                state: AccountState::Initialized,
+                ..Account::default()
+            };
+            Account::pack(account, &mut data).unwrap();
+            TokenAccount::try_deserialize(&mut data.as_ref()).unwrap()
+        };
+
+        // usdc_to_sol_trade spends 400 of 1_000 and leaves the holding undelegated
+        let holding = token_account(600, COption::None);
+        assert_eq!(check_swap_effects(1_000, 600, 400, &[&holding], &authority).unwrap(), 400);
+
+        // so a rebalance out of the same holding passes the same checks
+        let destination = token_account(0, COption::None);
+        let rebalanced = token_account(100, COption::None);
+        assert_eq!(check_swap_effects(600, 100, 500, &[&rebalanced, &destination], &authority).unwrap(), 500);
+
+        // A delegate left on the holding, as approving Jupiter did, fails the swap that leaves it
+        let delegated = token_account(600, COption::Some(Pubkey::new_unique()));
+        assert_eq!(
+            check_swap_effects(1_000, 600, 400, &[&delegated], &authority).unwrap_err(),
+            error!(FundError::RouteAccountNotAllowed)
+        );
+        assert_eq!(
+            check_swap_effects(1_000, 500, 400, &[&holding], &authority).unwrap_err(),
+            error!(FundError::SwapInputExceeded)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  createMint,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { IDL, MockSwap } from "../target/types/mock_swap";
+
+// Loaded at the Jupiter program id through [[test.genesis]] in Anchor.toml
+const JUPITER_PROGRAM_ID = new PublicKey(
+  "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4"
+);
+
+// Same formula as quote_out in programs/mock_swap
+function quoteOut(
+  amountIn: bigint,
+  reserveIn: bigint,
+  reserveOut: bigint,
+  feeBps: bigint
+): bigint {
+  const inAfterFee = (amountIn * (10_000n - feeBps)) / 10_000n;
+  return (inAfterFee * reserveOut) / (reserveIn + inAfterFee);
+}
+
+describe("mock_swap", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = new Program<MockSwap>(IDL, JUPITER_PROGRAM_ID, provider);
+  const payer = (provider.wallet as anchor.Wallet).payer;
+  const feeBps = 30;
+
+  let mintA: PublicKey;
+  let mintB: PublicKey;
+  let pool: PublicKey;
+  let vaultA: PublicKey;
+  let vaultB: PublicKey;
+  let userA: PublicKey;
+  let userB: PublicKey;
+
+  before(async () => {
+    mintA = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+    mintB = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+    [pool] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintA.toBuffer(), mintB.toBuffer()],
+      program.programId
+    );
+    [vaultA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_vault"), pool.toBuffer(), mintA.toBuffer()],
+      program.programId
+    );
+    [vaultB] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_vault"), pool.toBuffer(), mintB.toBuffer()],
+      program.programId
+    );
+
+    userA = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, mintA, payer.publicKey)
+    ).address;
+    userB = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, mintB, payer.publicKey)
+    ).address;
+    await mintTo(provider.connection, payer, mintA, userA, payer, 2_000_000);
+    await mintTo(provider.connection, payer, mintB, userB, payer, 2_000_000);
+
+    await program.methods
+      .initializePool(feeBps)
+      .accounts({ pool, mintA, mintB, vaultA, vaultB, payer: payer.publicKey })
+      .rpc();
+    await program.methods
+      .addLiquidity(new BN(1_000_000), new BN(1_000_000))
+      .accounts({
+        pool,
+        vaultA,
+        vaultB,
+        providerA: userA,
+        providerB: userB,
+        provider: payer.publicKey,
+      })
+      .rpc();
+  });
+
+  it("swaps at the constant-product quote", async () => {
+    const amountIn = 10_000n;
+    const expected = quoteOut(amountIn, 1_000_000n, 1_000_000n, BigInt(feeBps));
+    const sourceBefore = (await getAccount(provider.connection, userA)).amount;
+    const destinationBefore = (await getAccount(provider.connection, userB)).amount;
+
+    await program.methods
+      .swap(new BN(amountIn.toString()), new BN(expected.toString()))
+      .accounts({
+        pool,
+        authority: payer.publicKey,
+        source: userA,
+        destination: userB,
+        vaultA,
+        vaultB,
+      })
+      .rpc();
+
+    const sourceAfter = (await getAccount(provider.connection, userA)).amount;
+    const destinationAfter = (await getAccount(provider.connection, userB)).amount;
+    assert.equal(sourceBefore - sourceAfter, amountIn);
+    assert.equal(destinationAfter - destinationBefore, expected);
+    assert.equal((await getAccount(provider.connection, vaultA)).amount, 1_000_000n + amountIn);
+  });
+
+  it("rejects a swap below the minimum out", async () => {
+    const reserveA = (await getAccount(provider.connection, vaultA)).amount;
+    const reserveB = (await getAccount(provider.connection, vaultB)).amount;
+    const quote = quoteOut(10_000n, reserveA, reserveB, BigInt(feeBps));
+
+    try {
+      await program.methods
+        .swap(new BN(10_000), new BN((quote + 1n).toString()))
+        .accounts({
+          pool,
+          authority: payer.publicKey,
+          source: userA,
+          destination: userB,
+          vaultA,
+          vaultB,
+        })
+        .rpc();
+      assert.fail("swap should have failed");
+    } catch (e) {
+      assert.include(String(e), "SlippageExceeded");
+    }
+  });
+});